@@ -2,7 +2,6 @@
 //!
 
 use std::collections::HashMap;
-use std::iter::FromIterator;
 
 use rcombinators::combinators;
 use rcombinators::primitives;
@@ -39,16 +38,11 @@ impl Parser for ValueParser {
 }
 
 fn number() -> impl Parser<Result = Value> {
-    primitives::float().apply(|n| Ok(Value::Number(n)))
+    primitives::Float64::json().apply(|n| Ok(Value::Number(n)))
 }
 
 fn string() -> impl Parser<Result = Value> {
-    let quote = primitives::StringParser::new("\"");
-    let middle =
-        combinators::Lazy::new(|| primitives::string_none_of("\"", combinators::RepeatSpec::Any));
-    let string_with_quotes = combinators::Sequence::new((quote.clone(), middle, quote));
-    let string = string_with_quotes.apply(|(_, s, _)| Ok(Value::String(s)));
-    string
+    primitives::QuotedString::json().apply(|s| Ok(Value::String(s)))
 }
 
 fn list() -> impl Parser<Result = Value> {
@@ -59,23 +53,20 @@ fn list() -> impl Parser<Result = Value> {
     let inner = || {
         let val = ValueParser;
         let comma = primitives::StringParser::new(",");
-        let separated_element = combinators::Sequence::new((
+        let separated_element = combinators::delimited(
             primitives::whitespace(),
             val,
-            primitives::whitespace(),
-            combinators::Maybe::new(comma),
-        ));
-        let separated_element = separated_element.apply(|(_, v, _, _)| Ok(v));
-        let separated_elements =
-            combinators::Repeat::new(separated_element, combinators::RepeatSpec::Any);
-        separated_elements
+            combinators::Sequence::new((primitives::whitespace(), combinators::Maybe::new(comma))),
+        );
+        combinators::Repeat::new(separated_element, combinators::RepeatSpec::Any)
     };
-    let list = combinators::Sequence::new((open, combinators::Lazy::new(inner), close))
-        .apply(|(_, es, _)| Ok(Value::List(es)));
-    list
+    combinators::delimited(open, combinators::Lazy::new(inner), close)
+        .apply(|es| Ok(Value::List(es)))
 }
 
 fn dict() -> impl Parser<Result = Value> {
+    use combinators::Skip;
+
     let (open, close) = (
         primitives::StringParser::new("{"),
         primitives::StringParser::new("}"),
@@ -89,25 +80,20 @@ fn dict() -> impl Parser<Result = Value> {
             _ => panic!("unexpected value type in string position"),
         });
         let value = ValueParser;
-        let separated_element = combinators::Sequence::new((
-            primitives::whitespace(),
+        let separated_element = combinators::SkippingSequence::new((
+            Skip::new(primitives::whitespace()),
             key,
-            primitives::whitespace(),
-            sep,
-            primitives::whitespace(),
+            Skip::new(primitives::whitespace()),
+            Skip::new(sep),
+            Skip::new(primitives::whitespace()),
             value,
-            primitives::whitespace(),
-            combinators::Maybe::new(comma),
+            Skip::new(primitives::whitespace()),
+            Skip::new(combinators::Maybe::new(comma)),
         ));
-        let separated_element =
-            separated_element.apply(|(_ws1, k, _ws2, _sep, _ws3, v, _ws4, _comma)| Ok((k, v)));
-        let separated_elements =
-            combinators::Repeat::new(separated_element, combinators::RepeatSpec::Any);
-        separated_elements
+        combinators::Repeat::new(separated_element, combinators::RepeatSpec::Any).collect()
     };
-    let dict = combinators::Sequence::new((open, combinators::Lazy::new(inner), close))
-        .apply(|(_, es, _)| Ok(Value::Dict(HashMap::from_iter(es.into_iter()))));
-    dict
+    combinators::delimited(open, combinators::Lazy::new(inner), close)
+        .apply(|es| Ok(Value::Dict(es)))
 }
 
 #[macro_use]
@@ -117,6 +103,8 @@ extern crate time_test;
 mod tests {
     use crate::*;
 
+    use std::iter::FromIterator;
+
     #[test]
     fn test_number() {
         let mut ps = ParseState::new("-1.2e0");
@@ -125,11 +113,19 @@ mod tests {
 
     #[test]
     fn test_string() {
-        let mut ps = ParseState::new("\"Hello, World\n\"");
+        let mut ps = ParseState::new(r#""Hello, World\n""#);
         assert_eq!(
             Ok(Value::String("Hello, World\n".to_string())),
             string().parse(&mut ps)
         );
+        let mut ps = ParseState::new(r#""a\"b\n\u00e4\ud83d\ude00""#);
+        assert_eq!(
+            Ok(Value::String("a\"b\nä😀".to_string())),
+            string().parse(&mut ps)
+        );
+        for s in &[r#""\u{41}""#, r#""\'""#, r#""\0""#, "\"a\nb\"", "\"\t\""] {
+            assert!(string().parse(&mut ParseState::new(s)).is_err());
+        }
     }
 
     #[test]
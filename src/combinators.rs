@@ -1,5 +1,32 @@
-use crate::parser::{ParseError, ParseResult, Parser};
-use crate::state::ParseState;
+use crate::parser::{execerr, ParseError, ParseResult, Parser};
+use crate::state::{MemoKey, ParseState};
+
+use std::any::Any;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Run `f`, and restore any input consumed by it if it fails.
+///
+/// All combinators in this module are atomic: they either succeed, or fail without having consumed
+/// any input, as long as their sub-parsers are atomic, too. This is only needed by combinators that
+/// may fail after a sub-parser consumed input, e.g. `Then`; the others just pass on results.
+fn atomically<R, Iter: Iterator<Item = char>>(
+    st: &mut ParseState<Iter>,
+    f: impl FnOnce(&mut ParseState<Iter>) -> ParseResult<R>,
+) -> ParseResult<R> {
+    let hold = st.hold();
+    match f(st) {
+        Ok(r) => {
+            st.release(hold);
+            Ok(r)
+        }
+        Err(e) => {
+            st.reset(hold);
+            Err(e)
+        }
+    }
+}
 
 /// Transform applies a function (which may fail) to the result of a parser. Transform only
 /// succeeds if the applied function succeeds, too.
@@ -11,7 +38,7 @@ pub struct Transform<R, R2, P: Parser<Result = R>, F: Fn(R) -> ParseResult<R2>>
 impl<R, R2, P: Parser<Result = R>, F: Fn(R) -> ParseResult<R2>> Transform<R, R2, P, F> {
     /// Create a new Transform parser using f.
     pub fn new(p: P, f: F) -> Transform<R, R2, P, F> {
-        Transform { f: f, p: p }
+        Transform { f, p }
     }
 }
 
@@ -21,13 +48,53 @@ impl<R, R2, P: Parser<Result = R>, F: Fn(R) -> ParseResult<R2>> Parser for Trans
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        match self.p.parse(st) {
-            Ok(o) => (self.f)(o),
-            Err(e) => Err(e),
+        let (p, f) = (&mut self.p, &self.f);
+        atomically(st, |st| p.parse(st).and_then(f))
+    }
+}
+
+/// TransformWithState applies a function (which may fail) to the result of a parser, giving it
+/// access to the user state of type `S` stored in the `ParseState` (see
+/// `ParseState::set_user_state()`). It fails if no user state of type `S` has been set.
+pub struct TransformWithState<R, R2, S, P: Parser<Result = R>, F: Fn(R, &mut S) -> ParseResult<R2>>
+{
+    f: F,
+    p: P,
+    _s: PhantomData<fn(&mut S)>,
+}
+
+impl<R, R2, S, P: Parser<Result = R>, F: Fn(R, &mut S) -> ParseResult<R2>>
+    TransformWithState<R, R2, S, P, F>
+{
+    /// Create a new TransformWithState parser using f.
+    pub fn new(p: P, f: F) -> TransformWithState<R, R2, S, P, F> {
+        TransformWithState {
+            f,
+            p,
+            _s: PhantomData,
         }
     }
 }
 
+impl<R, R2, S: Any, P: Parser<Result = R>, F: Fn(R, &mut S) -> ParseResult<R2>> Parser
+    for TransformWithState<R, R2, S, P, F>
+{
+    type Result = R2;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let (p, f) = (&mut self.p, &self.f);
+        atomically(st, |st| {
+            let o = p.parse(st)?;
+            match st.user_state_mut() {
+                Some(s) => f(o, s),
+                None => Err(execerr("user state not set or of wrong type")),
+            }
+        })
+    }
+}
+
 pub struct Alternative<T>(T);
 
 impl<T> Alternative<T> {
@@ -94,11 +161,342 @@ alt_impl!((
     P8 / 8,
     P9 / 9
 ));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10
+));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11
+));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12
+));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13
+));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14
+));
+alt_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14,
+    P15 / 15
+));
+
+/// Either is the result of `OrEither`: it holds the result of whichever of two parsers succeeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// OrEither tries parser `A`, and if that fails, parser `B`. Unlike `Alternative`, the two parsers
+/// may have different result types; the result is wrapped in an `Either`. Usually constructed
+/// using `Parser::or_either()`.
+pub struct OrEither<A: Parser, B: Parser> {
+    a: A,
+    b: B,
+}
+
+impl<A: Parser, B: Parser> OrEither<A, B> {
+    pub fn new(a: A, b: B) -> OrEither<A, B> {
+        OrEither { a, b }
+    }
+}
+
+impl<A: Parser, B: Parser> Parser for OrEither<A, B> {
+    type Result = Either<A::Result, B::Result>;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        if let Ok(o) = atomically(st, |st| self.a.parse(st)) {
+            return Ok(Either::Left(o));
+        }
+        match atomically(st, |st| self.b.parse(st)) {
+            Ok(o) => Ok(Either::Right(o)),
+            Err(_) => Err(ParseError::Fail("no alternative matched", st.index())),
+        }
+    }
+}
+
+/// Choice is like `Alternative`, but its parsers may have different result types. T is a tuple of
+/// parsers; the result is a `OneOfN` enum (`N` being the length of the tuple) whose variant tells
+/// which parser succeeded.
+///
+/// ```
+/// use rcombinators::combinators::{Choice, OneOf2};
+/// use rcombinators::primitives::{Int64, StringParser};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("true12");
+/// let mut p = Choice::new((Int64::new(), StringParser::new("true")));
+/// assert_eq!(Ok(OneOf2::B("true".to_string())), p.parse(&mut ps));
+/// assert_eq!(Ok(OneOf2::A(12)), p.parse(&mut ps));
+/// ```
+pub struct Choice<T>(T);
+
+impl<T> Choice<T> {
+    pub fn new(tuple: T) -> Choice<T> {
+        Choice(tuple)
+    }
+}
+
+/// Macro for defining a OneOfN enum and implementing the Choice parser for the corresponding
+/// tuples. Not for public use.
+macro_rules! choice_impl {
+    ( $name:ident ( $($ptype:ident/$ix:tt/$var:ident),+ ) ) => {
+        /// Result of a `Choice` parser: one variant per parser in the tuple, in order.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name<$($ptype,)*> {
+            $($var($ptype),)*
+        }
+
+        impl<$($ptype : Parser, )*> Parser for Choice<($($ptype,)*)> {
+            type Result = $name<$($ptype::Result,)*>;
+            fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
+                $(
+                    if let Ok(o) = atomically(st, |st| (self.0).$ix.parse(st)) {
+                        return Ok($name::$var(o));
+                    }
+                )*
+                Err(ParseError::Fail("no alternative matched", st.index()))
+            }
+        }
+    }
+}
+
+choice_impl!(OneOf2(P0 / 0 / A, P1 / 1 / B));
+choice_impl!(OneOf3(P0 / 0 / A, P1 / 1 / B, P2 / 2 / C));
+choice_impl!(OneOf4(P0 / 0 / A, P1 / 1 / B, P2 / 2 / C, P3 / 3 / D));
+choice_impl!(OneOf5(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E
+));
+choice_impl!(OneOf6(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F
+));
+choice_impl!(OneOf7(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G
+));
+choice_impl!(OneOf8(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H
+));
+choice_impl!(OneOf9(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I
+));
+choice_impl!(OneOf10(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J
+));
+choice_impl!(OneOf11(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K
+));
+choice_impl!(OneOf12(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K,
+    P11 / 11 / L
+));
+choice_impl!(OneOf13(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K,
+    P11 / 11 / L,
+    P12 / 12 / M
+));
+choice_impl!(OneOf14(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K,
+    P11 / 11 / L,
+    P12 / 12 / M,
+    P13 / 13 / N
+));
+choice_impl!(OneOf15(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K,
+    P11 / 11 / L,
+    P12 / 12 / M,
+    P13 / 13 / N,
+    P14 / 14 / O
+));
+choice_impl!(OneOf16(
+    P0 / 0 / A,
+    P1 / 1 / B,
+    P2 / 2 / C,
+    P3 / 3 / D,
+    P4 / 4 / E,
+    P5 / 5 / F,
+    P6 / 6 / G,
+    P7 / 7 / H,
+    P8 / 8 / I,
+    P9 / 9 / J,
+    P10 / 10 / K,
+    P11 / 11 / L,
+    P12 / 12 / M,
+    P13 / 13 / N,
+    P14 / 14 / O,
+    P15 / 15 / P
+));
 
 /// Sequence concatenates parsers and only succeeds if all of them do. T is always a tuple in order
 /// for Sequence to implement the Parser trait. The result is a tuple of all the parser results.
 ///
-/// Individual parsers need to have result types implementing Default.
+/// Tuples of up to 16 parsers are supported. For longer sequences, nest `Sequence`s and use
+/// `Parser::flatten()` to obtain a flat result tuple.
 pub struct Sequence<T>(T);
 
 impl<T> Sequence<T> {
@@ -110,21 +508,21 @@ impl<T> Sequence<T> {
 /// Macro for implementing sequence parsers for arbitrary tuples. Not for public use.
 macro_rules! seq_impl {
     ( ( $($ptype:ident/$ix:tt),+ ) ) => {
-        impl<$($ptype : Parser<Result=impl Default>, )*> Parser for Sequence<($($ptype,)*)> {
+        impl<$($ptype : Parser, )*> Parser for Sequence<($($ptype,)*)> {
             type Result = ($($ptype::Result,)*);
             fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
                 let hold = st.hold();
-                let mut result = Self::Result::default();
-                $(
-                    let r = (self.0).$ix.parse(st);
-                    if r.is_err() {
-                        st.reset(hold);
-                        return Err(r.err().unwrap());
-                    }
-                    result.$ix = r.unwrap();
-                )*
+                let result = ($(
+                    match (self.0).$ix.parse(st) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            st.reset(hold);
+                            return Err(e);
+                        }
+                    },
+                )*);
                 st.release(hold);
-                return Ok(result);
+                Ok(result)
             }
         }
     }
@@ -169,48 +567,7 @@ seq_impl!((
     P8 / 8,
     P9 / 9
 ));
-
-/// PartialSequence concatenates parsers and tries to parse as far as possible.
-///
-/// Individual parsers need to have result types implementing Default.
-pub struct PartialSequence<T>(T);
-
-impl<T> PartialSequence<T> {
-    pub fn new(tuple: T) -> PartialSequence<T> {
-        PartialSequence(tuple)
-    }
-}
-
-/// Macro for implementing sequence parsers for arbitrary tuples. Not for public use.
-macro_rules! pseq_impl {
-    ( ( $($ptype:ident/$ix:tt),+ ) ) => {
-        impl<$($ptype : Parser<Result=impl Default>, )*> Parser for PartialSequence<($($ptype,)*)> {
-            type Result = ($(Option<$ptype::Result>,)*);
-            fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
-                let hold = st.hold();
-                let mut result = Self::Result::default();
-                $(
-                    let r = (self.0).$ix.parse(st);
-                    if r.is_err() {
-                        st.release(hold);
-                        return Ok(result);
-                    }
-                    result.$ix = Some(r.unwrap());
-                )*
-                st.release(hold);
-                return Ok(result);
-            }
-        }
-    }
-}
-
-pseq_impl!((P0 / 0, P1 / 1));
-pseq_impl!((P0 / 0, P1 / 1, P2 / 2));
-pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3));
-pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4));
-pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5));
-pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5, P6 / 6));
-pseq_impl!((
+seq_impl!((
     P0 / 0,
     P1 / 1,
     P2 / 2,
@@ -218,9 +575,12 @@ pseq_impl!((
     P4 / 4,
     P5 / 5,
     P6 / 6,
-    P7 / 7
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10
 ));
-pseq_impl!((
+seq_impl!((
     P0 / 0,
     P1 / 1,
     P2 / 2,
@@ -229,9 +589,12 @@ pseq_impl!((
     P5 / 5,
     P6 / 6,
     P7 / 7,
-    P8 / 8
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11
 ));
-pseq_impl!((
+seq_impl!((
     P0 / 0,
     P1 / 1,
     P2 / 2,
@@ -241,64 +604,1100 @@ pseq_impl!((
     P6 / 6,
     P7 / 7,
     P8 / 8,
-    P9 / 9
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12
+));
+seq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13
+));
+seq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14
+));
+seq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14,
+    P15 / 15
 ));
 
-pub enum RepeatSpec {
-    /// Any is equivalent to Min(0).
-    Any,
-    Min(usize),
-    Max(usize),
-    Between(usize, usize),
+/// SkippingSequence is a `Sequence` whose result only contains the results of elements not
+/// wrapped in `Skip`. This keeps whitespace and punctuation out of the result tuple.
+///
+/// Rust can't tell from a parser's type whether its result is `()`, so such parsers must be wrapped
+/// in `Skip` explicitly as well if their results are to be dropped.
+///
+/// ```
+/// use rcombinators::combinators::{Skip, SkippingSequence};
+/// use rcombinators::primitives::{whitespace, Int64, StringParser};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("(1, 2)");
+/// let mut p = SkippingSequence::new((
+///     Skip::new(StringParser::new("(")),
+///     Int64::new(),
+///     Skip::new(StringParser::new(",")),
+///     Skip::new(whitespace()),
+///     Int64::new(),
+///     Skip::new(StringParser::new(")")),
+/// ));
+/// assert_eq!(Ok((1, 2)), p.parse(&mut ps));
+/// ```
+pub struct SkippingSequence<T>(T);
+
+impl<T> SkippingSequence<T> {
+    pub fn new(tuple: T) -> SkippingSequence<T> {
+        SkippingSequence(tuple)
+    }
 }
 
-pub struct Repeat<P: Parser> {
-    inner: P,
-    repeat: RepeatSpec,
+/// Skip marks an element of a `SkippingSequence` whose result is dropped.
+pub struct Skip<P: Parser>(P);
+
+impl<P: Parser> Skip<P> {
+    pub fn new(p: P) -> Skip<P> {
+        Skip(p)
+    }
 }
 
-impl<P: Parser> Repeat<P> {
-    pub fn new(p: P, r: RepeatSpec) -> Repeat<P> {
-        Repeat {
-            inner: p,
-            repeat: r,
+/// Push appends an element to a tuple. It is implemented for tuples of up to 15 elements and used
+/// by `SkippingSequence` to build its result.
+pub trait Push<T> {
+    type Output;
+    fn push(self, t: T) -> Self::Output;
+}
+
+/// Macro for implementing Push for tuples. Not for public use.
+macro_rules! push_impl {
+    ( $($t:ident),* ) => {
+        impl<$($t, )* T> Push<T> for ($($t,)*) {
+            type Output = ($($t,)* T,);
+            #[allow(non_snake_case)]
+            fn push(self, t: T) -> Self::Output {
+                let ($($t,)*) = self;
+                ($($t,)* t,)
+            }
         }
     }
 }
 
-impl<R, P: Parser<Result = R>> Parser for Repeat<P> {
-    type Result = Vec<R>;
-    fn parse(
+push_impl!();
+push_impl!(T0);
+push_impl!(T0, T1);
+push_impl!(T0, T1, T2);
+push_impl!(T0, T1, T2, T3);
+push_impl!(T0, T1, T2, T3, T4);
+push_impl!(T0, T1, T2, T3, T4, T5);
+push_impl!(T0, T1, T2, T3, T4, T5, T6);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+push_impl!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
+
+/// SequenceElement is implemented by all types that can be used as elements of a
+/// `SkippingSequence`: parsers, whose result is appended to the result tuple `Acc` built so far,
+/// and parsers wrapped in `Skip`, which leave it unchanged.
+pub trait SequenceElement<Acc> {
+    type Output;
+    fn parse_into(
         &mut self,
+        acc: Acc,
         st: &mut ParseState<impl Iterator<Item = char>>,
-    ) -> ParseResult<Self::Result> {
-        let (min, max) = match self.repeat {
-            RepeatSpec::Any => (0, std::usize::MAX),
-            RepeatSpec::Min(min) => (min as usize, std::usize::MAX),
-            RepeatSpec::Max(max) => (0, max as usize),
-            RepeatSpec::Between(min, max) => (min as usize, max as usize),
-        };
-        let mut v: Self::Result = Vec::new();
-        let hold = st.hold();
-        for i in 0.. {
-            match self.inner.parse(st) {
-                Ok(r) => v.push(r),
-                Err(e) => {
-                    if i >= min {
-                        st.release(hold);
-                        return Ok(v);
+    ) -> ParseResult<Self::Output>;
+}
+
+impl<Acc: Push<P::Result>, P: Parser> SequenceElement<Acc> for P {
+    type Output = Acc::Output;
+    fn parse_into(
+        &mut self,
+        acc: Acc,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Output> {
+        self.parse(st).map(|r| acc.push(r))
+    }
+}
+
+impl<Acc, P: Parser> SequenceElement<Acc> for Skip<P> {
+    type Output = Acc;
+    fn parse_into(
+        &mut self,
+        acc: Acc,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Output> {
+        self.0.parse(st).map(|_| acc)
+    }
+}
+
+/// Macro for implementing skipping sequence parsers for arbitrary tuples. It accumulates the
+/// bounds and the result type element by element. Not for public use.
+macro_rules! skipseq_impl {
+    ( ( $($ptype:ident/$ix:tt),+ ) ) => {
+        skipseq_impl!(@build [$($ptype/$ix),+] [] [] ());
+    };
+    ( @build [$p:ident/$i:tt $(, $rest:ident/$ri:tt)*] [$($all:ident/$aix:tt),*] [$($bounds:tt)*] $acc:ty ) => {
+        skipseq_impl!(
+            @build [$($rest/$ri),*] [$($all/$aix,)* $p/$i]
+            [$($bounds)* $p: SequenceElement<$acc>,] <$p as SequenceElement<$acc>>::Output
+        );
+    };
+    ( @build [] [$($all:ident/$aix:tt),*] [$($bounds:tt)*] $acc:ty ) => {
+        impl<$($all, )*> Parser for SkippingSequence<($($all,)*)> where $($bounds)* {
+            type Result = $acc;
+            fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
+                let hold = st.hold();
+                let acc = ();
+                $(
+                    let acc = match (self.0).$aix.parse_into(acc, st) {
+                        Ok(acc) => acc,
+                        Err(e) => {
+                            st.reset(hold);
+                            return Err(e);
+                        }
+                    };
+                )*
+                st.release(hold);
+                Ok(acc)
+            }
+        }
+    };
+}
+
+skipseq_impl!((P0 / 0, P1 / 1));
+skipseq_impl!((P0 / 0, P1 / 1, P2 / 2));
+skipseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3));
+skipseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4));
+skipseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5));
+skipseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5, P6 / 6));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14
+));
+skipseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14,
+    P15 / 15
+));
+
+/// Flatten turns a pair of tuples into a single tuple, e.g. `((a, b), (c, d, e))` into
+/// `(a, b, c, d, e)`. It is implemented for all pairs of tuples with up to 16 elements each.
+pub trait Flatten {
+    type Output;
+    fn flatten(self) -> Self::Output;
+}
+
+/// Macro for implementing Flatten for a pair of tuples. Not for public use.
+macro_rules! flatten_impl {
+    ( ( $($a:ident),+ ), ( $($b:ident),+ ) ) => {
+        impl<$($a, )* $($b, )*> Flatten for (($($a,)*), ($($b,)*)) {
+            type Output = ($($a,)* $($b,)*);
+            #[allow(non_snake_case)]
+            fn flatten(self) -> Self::Output {
+                let (($($a,)*), ($($b,)*)) = self;
+                ($($a,)* $($b,)*)
+            }
+        }
+    }
+}
+
+/// Macro for implementing Flatten for a tuple combined with all tuples of up to the given length.
+/// Not for public use.
+macro_rules! flatten_rhs {
+    ( ( $($a:ident),+ ); ( $b0:ident ) ) => {
+        flatten_impl!(($($a),*), ($b0));
+    };
+    ( ( $($a:ident),+ ); ( $b0:ident, $($b:ident),+ ) ) => {
+        flatten_impl!(($($a),*), ($b0, $($b),*));
+        flatten_rhs!(($($a),*); ($($b),*));
+    };
+}
+
+flatten_rhs!((T0); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+flatten_rhs!((T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15); (U0, U1, U2, U3, U4, U5, U6, U7, U8, U9, U10, U11, U12, U13, U14, U15));
+
+/// Flat flattens the result of a parser returning a pair of tuples, as produced by nesting two
+/// `Sequence`s in another one. Usually constructed using `Parser::flatten()`.
+///
+/// ```
+/// use rcombinators::combinators::Sequence;
+/// use rcombinators::primitives::{Int64, StringParser};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("1,2;");
+/// let mut p = Sequence::new((
+///     Sequence::new((Int64::new(), StringParser::new(","))),
+///     Sequence::new((Int64::new(), StringParser::new(";"))),
+/// ))
+/// .flatten();
+/// assert_eq!(
+///     Ok((1, ",".to_string(), 2, ";".to_string())),
+///     p.parse(&mut ps)
+/// );
+/// ```
+pub struct Flat<P: Parser>(P);
+
+impl<P: Parser> Flat<P> {
+    pub fn new(p: P) -> Flat<P> {
+        Flat(p)
+    }
+}
+
+impl<P: Parser> Parser for Flat<P>
+where
+    P::Result: Flatten,
+{
+    type Result = <P::Result as Flatten>::Output;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        self.0.parse(st).map(Flatten::flatten)
+    }
+}
+
+/// PartialSequence concatenates parsers and tries to parse as far as possible. The result is a
+/// tuple of `Option`s, which are `None` for the first parser that failed and all following ones.
+pub struct PartialSequence<T>(T);
+
+impl<T> PartialSequence<T> {
+    pub fn new(tuple: T) -> PartialSequence<T> {
+        PartialSequence(tuple)
+    }
+}
+
+/// Macro for implementing sequence parsers for arbitrary tuples. Not for public use.
+macro_rules! pseq_impl {
+    ( ( $($ptype:ident/$ix:tt),+ ) ) => {
+        impl<$($ptype : Parser, )*> Parser for PartialSequence<($($ptype,)*)> {
+            type Result = ($(Option<$ptype::Result>,)*);
+            // The last element's assignment to `failed` is never read.
+            #[allow(unused_assignments)]
+            fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
+                let mut failed = false;
+                Ok(($(
+                    if failed {
+                        None
                     } else {
-                        st.reset(hold);
-                        return Err(e);
+                        let r = atomically(st, |st| (self.0).$ix.parse(st)).ok();
+                        failed = r.is_none();
+                        r
+                    },
+                )*))
+            }
+        }
+    }
+}
+
+pseq_impl!((P0 / 0, P1 / 1));
+pseq_impl!((P0 / 0, P1 / 1, P2 / 2));
+pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3));
+pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4));
+pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5));
+pseq_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5, P6 / 6));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14
+));
+pseq_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14,
+    P15 / 15
+));
+
+/// Permutation parses the elements of a tuple in any order, each at most once. This is useful
+/// for attribute lists or option blocks. The result is a tuple of the elements' results in
+/// declaration order.
+///
+/// Elements are either parsers, which are required, or optional parsers wrapped in `Optional`,
+/// whose results are `Option`s. If a required element is not found, Permutation fails with
+/// `ParseError::MissingElement` referring to the first missing element's index.
+///
+/// ```
+/// use rcombinators::combinators::{Optional, Permutation};
+/// use rcombinators::primitives::StringParser;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("cab");
+/// let mut p = Permutation::new((
+///     StringParser::new("a"),
+///     StringParser::new("b"),
+///     Optional::new(StringParser::new("c")),
+///     Optional::new(StringParser::new("d")),
+/// ));
+/// assert_eq!(
+///     Ok(("a".to_string(), "b".to_string(), Some("c".to_string()), None)),
+///     p.parse(&mut ps)
+/// );
+/// ```
+pub struct Permutation<T>(T);
+
+impl<T> Permutation<T> {
+    pub fn new(tuple: T) -> Permutation<T> {
+        Permutation(tuple)
+    }
+}
+
+/// Optional marks an element of a `Permutation` as optional.
+pub struct Optional<P: Parser>(P);
+
+impl<P: Parser> Optional<P> {
+    pub fn new(p: P) -> Optional<P> {
+        Optional(p)
+    }
+}
+
+/// PermutationElement is implemented by all types that can be used as elements of a
+/// `Permutation`: all parsers, and parsers wrapped in `Optional`.
+pub trait PermutationElement {
+    /// The result of the element's parser.
+    type Result;
+    /// The result as it appears in the permutation's result.
+    type Output;
+
+    fn parse_element(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result>;
+    /// Convert the result of the element, if it was found. Returns `None` if the element is
+    /// required but was not found.
+    fn finish(r: Option<Self::Result>) -> Option<Self::Output>;
+}
+
+impl<P: Parser> PermutationElement for P {
+    type Result = P::Result;
+    type Output = P::Result;
+
+    fn parse_element(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        self.parse(st)
+    }
+    fn finish(r: Option<Self::Result>) -> Option<Self::Output> {
+        r
+    }
+}
+
+impl<P: Parser> PermutationElement for Optional<P> {
+    type Result = P::Result;
+    type Output = Option<P::Result>;
+
+    fn parse_element(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        self.0.parse(st)
+    }
+    fn finish(r: Option<Self::Result>) -> Option<Self::Output> {
+        Some(r)
+    }
+}
+
+/// Macro for implementing permutation parsers for arbitrary tuples. Not for public use.
+macro_rules! perm_impl {
+    ( ( $($ptype:ident/$ix:tt),+ ) ) => {
+        impl<$($ptype : PermutationElement, )*> Parser for Permutation<($($ptype,)*)> {
+            type Result = ($($ptype::Output,)*);
+            fn parse(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<Self::Result> {
+                let hold = st.hold();
+                let mut results = ($(Option::<$ptype::Result>::None,)*);
+                loop {
+                    let mut progress = false;
+                    $(
+                        if results.$ix.is_none() {
+                            let h = st.hold();
+                            match (self.0).$ix.parse_element(st) {
+                                Ok(r) => {
+                                    st.release(h);
+                                    results.$ix = Some(r);
+                                    progress = true;
+                                }
+                                Err(_) => st.reset(h),
+                            }
+                        }
+                    )*
+                    if !progress {
+                        break;
                     }
                 }
+                let ix = st.index();
+                let result = ($(
+                    match $ptype::finish(results.$ix) {
+                        Some(o) => o,
+                        None => {
+                            st.reset(hold);
+                            return Err(ParseError::MissingElement($ix, ix));
+                        }
+                    },
+                )*);
+                st.release(hold);
+                Ok(result)
+            }
+        }
+    }
+}
+
+perm_impl!((P0 / 0, P1 / 1));
+perm_impl!((P0 / 0, P1 / 1, P2 / 2));
+perm_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3));
+perm_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4));
+perm_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5));
+perm_impl!((P0 / 0, P1 / 1, P2 / 2, P3 / 3, P4 / 4, P5 / 5, P6 / 6));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14
+));
+perm_impl!((
+    P0 / 0,
+    P1 / 1,
+    P2 / 2,
+    P3 / 3,
+    P4 / 4,
+    P5 / 5,
+    P6 / 6,
+    P7 / 7,
+    P8 / 8,
+    P9 / 9,
+    P10 / 10,
+    P11 / 11,
+    P12 / 12,
+    P13 / 13,
+    P14 / 14,
+    P15 / 15
+));
+
+pub enum RepeatSpec {
+    /// Any is equivalent to Min(0).
+    Any,
+    Min(usize),
+    Max(usize),
+    Between(usize, usize),
+    /// Exactly(n) is equivalent to Between(n, n).
+    Exactly(usize),
+}
+
+impl RepeatSpec {
+    /// Return minimum and maximum number of repetitions.
+    fn bounds(&self) -> (usize, usize) {
+        match *self {
+            RepeatSpec::Any => (0, usize::MAX),
+            RepeatSpec::Min(min) => (min, usize::MAX),
+            RepeatSpec::Max(max) => (0, max),
+            RepeatSpec::Between(min, max) => (min, max),
+            RepeatSpec::Exactly(n) => (n, n),
+        }
+    }
+}
+
+/// Apply `p` as often as allowed by `spec`, combining the results into `init` using `f`. This is
+/// the implementation of `Repeat`, `Fold` and `Collect`.
+///
+/// If `p` succeeds without consuming any input, it would do so forever; in that case, repetition
+/// stops (after the minimum number of repetitions has been reached).
+fn repeat_fold<P: Parser, A>(
+    p: &mut P,
+    spec: &RepeatSpec,
+    st: &mut ParseState<impl Iterator<Item = char>>,
+    init: A,
+    mut f: impl FnMut(A, P::Result) -> A,
+) -> ParseResult<A> {
+    let (min, max) = spec.bounds();
+    let mut acc = init;
+    let mut n = 0;
+    let hold = st.hold();
+    while n < max {
+        let start = st.index();
+        match p.parse(st) {
+            Ok(_) if st.index() == start && n >= min => break,
+            Ok(r) => {
+                acc = f(acc, r);
+                n += 1;
+            }
+            Err(e) => {
+                if n >= min {
+                    break;
+                }
+                st.reset(hold);
+                return Err(e);
             }
-            if i >= max - 1 {
+        }
+    }
+    st.release(hold);
+    Ok(acc)
+}
+
+/// Repeat applies a parser repeatedly, as specified by a `RepeatSpec`, and returns the results as
+/// `Vec`. Use `fold()` or `collect()` to combine the results differently.
+pub struct Repeat<P: Parser> {
+    inner: P,
+    repeat: RepeatSpec,
+}
+
+impl<P: Parser> Repeat<P> {
+    pub fn new(p: P, r: RepeatSpec) -> Repeat<P> {
+        Repeat {
+            inner: p,
+            repeat: r,
+        }
+    }
+
+    /// Combine the results using `f`, starting with `init` every time the parser is invoked,
+    /// instead of returning them as `Vec`.
+    pub fn fold<A: Clone, F: Fn(A, P::Result) -> A>(self, init: A, f: F) -> Fold<P, A, F> {
+        Fold {
+            inner: self.inner,
+            repeat: self.repeat,
+            init,
+            f,
+        }
+    }
+
+    /// Collect the results into a collection of type `C` (e.g. a `String` or a `HashMap`) instead
+    /// of a `Vec`.
+    pub fn collect<C: Default + Extend<P::Result>>(self) -> Collect<P, C> {
+        Collect {
+            inner: self.inner,
+            repeat: self.repeat,
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<R, P: Parser<Result = R>> Parser for Repeat<P> {
+    type Result = Vec<R>;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        repeat_fold(&mut self.inner, &self.repeat, st, Vec::new(), |mut v, r| {
+            v.push(r);
+            v
+        })
+    }
+}
+
+/// Fold applies a parser repeatedly like `Repeat`, but combines the results using a function
+/// instead of collecting them. Construct it using `Repeat::fold()`.
+pub struct Fold<P: Parser, A: Clone, F: Fn(A, P::Result) -> A> {
+    inner: P,
+    repeat: RepeatSpec,
+    init: A,
+    f: F,
+}
+
+impl<P: Parser, A: Clone, F: Fn(A, P::Result) -> A> Parser for Fold<P, A, F> {
+    type Result = A;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        repeat_fold(
+            &mut self.inner,
+            &self.repeat,
+            st,
+            self.init.clone(),
+            &self.f,
+        )
+    }
+}
+
+/// Collect applies a parser repeatedly like `Repeat`, but collects the results into any
+/// collection implementing `Extend`. Construct it using `Repeat::collect()`.
+pub struct Collect<P: Parser, C: Default + Extend<P::Result>> {
+    inner: P,
+    repeat: RepeatSpec,
+    _c: PhantomData<fn() -> C>,
+}
+
+impl<P: Parser, C: Default + Extend<P::Result>> Parser for Collect<P, C> {
+    type Result = C;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        repeat_fold(
+            &mut self.inner,
+            &self.repeat,
+            st,
+            C::default(),
+            |mut c, r| {
+                c.extend(Some(r));
+                c
+            },
+        )
+    }
+}
+
+/// Count applies a parser exactly `N` times, and returns the results as array. Use `count()` to
+/// construct it.
+pub struct Count<P: Parser, const N: usize>(P);
+
+/// Apply `p` exactly `N` times, e.g. `count::<4, _>(p)`. See `Count`.
+pub fn count<const N: usize, P: Parser>(p: P) -> Count<P, N> {
+    Count(p)
+}
+
+impl<P: Parser, const N: usize> Parser for Count<P, N> {
+    type Result = [P::Result; N];
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let v = repeat_fold(
+            &mut self.0,
+            &RepeatSpec::Exactly(N),
+            st,
+            Vec::with_capacity(N),
+            |mut v, r| {
+                v.push(r);
+                v
+            },
+        )?;
+        match <[P::Result; N]>::try_from(v) {
+            Ok(a) => Ok(a),
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+/// ManyTill applies a parser repeatedly until a terminating parser succeeds, returning the
+/// results of both. The terminator is tried first in every iteration, so `ManyTill` may return
+/// zero results. It fails if the repeated parser fails before the terminator was found, or if it
+/// succeeds without consuming any input (which would repeat forever).
+///
+/// To consume everything up to a literal terminator, `primitives::take_until()` is much faster.
+pub struct ManyTill<P: Parser, T: Parser> {
+    inner: P,
+    end: T,
+}
+
+impl<P: Parser, T: Parser> ManyTill<P, T> {
+    pub fn new(p: P, end: T) -> ManyTill<P, T> {
+        ManyTill { inner: p, end }
+    }
+}
+
+impl<P: Parser, T: Parser> Parser for ManyTill<P, T> {
+    type Result = (Vec<P::Result>, T::Result);
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let hold = st.hold();
+        let mut v = vec![];
+        loop {
+            if let Ok(end) = self.end.parse(st) {
                 st.release(hold);
-                return Ok(v);
+                return Ok((v, end));
+            }
+            let start = st.index();
+            match self.inner.parse(st) {
+                Ok(_) if st.index() == start => {
+                    st.reset(hold);
+                    return Err(ParseError::Fail("no progress in repetition", start));
+                }
+                Ok(r) => v.push(r),
+                Err(e) => {
+                    st.reset(hold);
+                    return Err(e);
+                }
             }
         }
-        unreachable!()
     }
 }
 
@@ -346,86 +1745,451 @@ impl<R, P: Parser<Result = R>> Parser for Ignore<P> {
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        match self.inner.parse(st) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+        self.inner.parse(st).map(|_| ())
+    }
+}
+
+/// Applies one parser, discards the result, and returns the second parser's results if the first
+/// one succeeded. To skip the input consumed by several parsers, use a `Sequence` combinators as
+/// `A`.
+pub struct Then<A: Parser, B: Parser> {
+    a: A,
+    b: B,
+}
+
+impl<A: Parser, B: Parser> Then<A, B> {
+    pub fn new(first: A, second: B) -> Then<A, B> {
+        Then {
+            a: first,
+            b: second,
+        }
+    }
+}
+
+impl<A: Parser, B: Parser> Parser for Then<A, B> {
+    type Result = B::Result;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let (a, b) = (&mut self.a, &mut self.b);
+        atomically(st, |st| a.parse(st).and_then(|_| b.parse(st)))
+    }
+}
+
+/// Terminated applies two parsers and returns the first one's result if both succeed. Usually
+/// constructed using `terminated()`.
+pub struct Terminated<A: Parser, B: Parser> {
+    a: A,
+    b: B,
+}
+
+impl<A: Parser, B: Parser> Terminated<A, B> {
+    pub fn new(first: A, second: B) -> Terminated<A, B> {
+        Terminated {
+            a: first,
+            b: second,
+        }
+    }
+}
+
+impl<A: Parser, B: Parser> Parser for Terminated<A, B> {
+    type Result = A::Result;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let (a, b) = (&mut self.a, &mut self.b);
+        atomically(st, |st| {
+            let r = a.parse(st)?;
+            b.parse(st).map(|_| r)
+        })
+    }
+}
+
+/// Parse `a` and then `p`, returning only `p`'s result. This is the same as `a.then(p)`.
+pub fn preceded<A: Parser, P: Parser>(a: A, p: P) -> Then<A, P> {
+    Then::new(a, p)
+}
+
+/// Parse `p` and then `b`, returning only `p`'s result.
+pub fn terminated<P: Parser, B: Parser>(p: P, b: B) -> Terminated<P, B> {
+    Terminated::new(p, b)
+}
+
+/// Parse `open`, `p` and `close`, returning only `p`'s result. For example, use
+/// `delimited(StringParser::new("("), p, StringParser::new(")"))` for parenthesized expressions.
+pub fn delimited<O: Parser, P: Parser, C: Parser>(
+    open: O,
+    p: P,
+    close: C,
+) -> Then<O, Terminated<P, C>> {
+    Then::new(open, Terminated::new(p, close))
+}
+
+/// Bind applies one parser, and then the parser returned by a function for the first parser's
+/// result, returning the second parser's result. If the second parser fails, the input consumed
+/// by the first one is restored, too. Usually constructed using `Parser::and_then()`.
+pub struct Bind<A: Parser, B: Parser, F: Fn(A::Result) -> B> {
+    a: A,
+    f: F,
+}
+
+impl<A: Parser, B: Parser, F: Fn(A::Result) -> B> Bind<A, B, F> {
+    pub fn new(first: A, f: F) -> Bind<A, B, F> {
+        Bind { a: first, f }
+    }
+}
+
+impl<A: Parser, B: Parser, F: Fn(A::Result) -> B> Parser for Bind<A, B, F> {
+    type Result = B::Result;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let hold = st.hold();
+        let r = match self.a.parse(st) {
+            Ok(r) => r,
+            Err(e) => {
+                st.reset(hold);
+                return Err(e);
+            }
+        };
+        match (self.f)(r).parse(st) {
+            Ok(r) => {
+                st.release(hold);
+                Ok(r)
+            }
+            Err(e) => {
+                st.reset(hold);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Lazy is a helper for a typical situation where you have an `Alternative` or a `Sequence` and
+/// don't want to construct an expensive parser every time just in order for it to be dropped
+/// without having parsed anything. For example:
+///
+/// ```ignore
+/// // Let's say dict is really expensive! the first ones not as much
+/// let mut p = Alternative::new((number(), string(), atom(), dict()));
+/// ```
+///
+/// Then you can wrap the `dict` parser constructor in a `Lazy` parser. Then it will only be
+/// constructed if the `Alternative` actually needs a `dict` parser:
+///
+/// ```ignore
+/// let mut p = Alternative::new((number(), string(), atom(), Lazy::new(dict)));
+/// ```
+///
+/// Constructing a `Lazy` combinator is in comparison quite cheap, as it only involves copying a
+/// function pointer. `Lazy` also caches the result of the function, meaning it will be called at
+/// most once.
+pub struct Lazy<P, F: FnMut() -> P>(F, Option<P>);
+
+impl<R, P: Parser<Result = R>, F: FnMut() -> P> Lazy<P, F> {
+    /// Create a new instance of `Lazy`:
+    ///
+    /// ```ignore
+    /// let l = Lazy::new(|| some_expensive_function());
+    /// ```
+    pub fn new(f: F) -> Lazy<P, F> {
+        Lazy(f, None)
+    }
+}
+
+impl<R, P: Parser<Result = R>, F: FnMut() -> P> Parser for Lazy<P, F> {
+    type Result = R;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        if self.1.is_none() {
+            self.1 = Some((self.0)());
+        }
+        self.1.as_mut().unwrap().parse(st)
+    }
+}
+
+/// Memo memoizes ("packrat parsing") the results of a parser: When it is invoked again at a
+/// position it has parsed before, for example because an enclosing `Alternative` backtracked, it
+/// returns the cached result (or error) and skips over the input consumed the first time, instead
+/// of parsing again.
+///
+/// The cache is stored in the `ParseState`, so a Memo is only worth it for parsers that are
+/// expensive and actually invoked repeatedly at the same position. Cached results are dropped once
+/// the input they refer to has been discarded by the `ParseState`.
+///
+/// Results are cached by input position only, so memoized parsers must not depend on anything but
+/// the input. In particular, the cache doesn't take into account the indentation of the enclosing
+/// `IndentedBlock` (`ParseState::indentation()`) or the user state, and changes to the user state
+/// are not replayed from the cache. If a memoized parser may be invoked at the same position with
+/// different indentation or user state, call `ParseState::clear_memo()` when these change.
+pub struct Memo<P: Parser> {
+    inner: P,
+    key: MemoKey,
+}
+
+/// Source of unique keys for Memo parsers.
+static NEXT_MEMO_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl<P: Parser> Memo<P> {
+    /// Create a Memo parser with its own cache.
+    pub fn new(p: P) -> Memo<P> {
+        Memo {
+            inner: p,
+            key: MemoKey::Id(NEXT_MEMO_ID.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+    /// Create a Memo parser sharing its cache with all other Memo parsers of the same name. This
+    /// is useful if parsers are constructed repeatedly, for example in a recursive grammar.
+    /// Parsers with the same name must be equivalent.
+    pub fn named(name: &'static str, p: P) -> Memo<P> {
+        Memo {
+            inner: p,
+            key: MemoKey::Name(name),
+        }
+    }
+}
+
+impl<R: Clone + 'static, P: Parser<Result = R>> Parser for Memo<P> {
+    type Result = R;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        if let Some((result, end)) = st.memo_get(self.key) {
+            st.skip_to(end);
+            return result;
+        }
+        let start = st.index();
+        let result = self.inner.parse(st);
+        let end = st.index();
+        st.memo_insert(self.key, start, result.clone(), end);
+        result
+    }
+}
+
+/// LeftRec makes a left-recursive rule like `expr := expr '-' term | term` usable, which would
+/// otherwise recurse forever. `P` is the rule's body; it refers to the rule itself by
+/// (indirectly) constructing another `LeftRec` with the same name, typically from a type
+/// implementing `Parser` by delegating to the `LeftRec`:
+///
+/// ```
+/// use rcombinators::combinators::{Alternative, LeftRec, Sequence};
+/// use rcombinators::primitives::{Int64, StringParser};
+/// use rcombinators::{ParseResult, ParseState, Parser};
+///
+/// struct Expr;
+///
+/// impl Parser for Expr {
+///     type Result = i64;
+///     fn parse(
+///         &mut self,
+///         st: &mut ParseState<impl Iterator<Item = char>>,
+///     ) -> ParseResult<Self::Result> {
+///         let sub = Sequence::new((Expr, StringParser::new("-"), Int64::new()))
+///             .apply(|(a, _, b)| Ok(a - b));
+///         LeftRec::new("expr", Alternative::new((sub, Int64::new()))).parse(st)
+///     }
+/// }
+///
+/// let mut ps = ParseState::new("10-2-3");
+/// assert_eq!(Ok(5), Expr.parse(&mut ps));
+/// ```
+///
+/// This works by "growing a seed" on top of the memoization used by `Memo`: The recursive
+/// invocation at the same position initially fails, so that the non-recursive alternative
+/// matches. The rule is then parsed again, with the recursive invocation returning the previous
+/// result, as long as this consumes more input than before.
+///
+/// Only direct left recursion is supported, i.e. the rule has to invoke itself (and not another
+/// left-recursive rule invoking it) at the position it started at.
+pub struct LeftRec<P: Parser> {
+    inner: P,
+    key: MemoKey,
+}
+
+impl<P: Parser> LeftRec<P> {
+    /// Create a left-recursive rule. All instances with the same name are treated as the same
+    /// rule, and must be equivalent.
+    pub fn new(name: &'static str, p: P) -> LeftRec<P> {
+        LeftRec {
+            inner: p,
+            key: MemoKey::Name(name),
+        }
+    }
+}
+
+impl<R: Clone + 'static, P: Parser<Result = R>> Parser for LeftRec<P> {
+    type Result = R;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        if let Some((result, end)) = st.memo_get(self.key) {
+            st.skip_to(end);
+            return result;
+        }
+        let start = st.index();
+        let mut result = Err(ParseError::Fail("left recursion", start));
+        let mut end = start;
+        st.memo_insert(self.key, start, result.clone(), end);
+        loop {
+            let hold = st.hold();
+            let r = self.inner.parse(st);
+            let new_end = st.index();
+            st.reset(hold);
+            match r {
+                Ok(o) if result.is_err() || new_end > end => {
+                    result = Ok(o);
+                    end = new_end;
+                }
+                Err(e) if result.is_err() => {
+                    result = Err(e);
+                    break;
+                }
+                _ => break,
+            }
+            st.memo_insert(self.key, start, result.clone(), end);
         }
+        st.memo_insert(self.key, start, result.clone(), end);
+        st.skip_to(end);
+        result
+    }
+}
+
+/// Skip spaces, tabs and newlines preceding an indented item.
+fn skip_layout(st: &mut ParseState<impl Iterator<Item = char>>) {
+    while let Some(' ') | Some('\t') | Some('\r') | Some('\n') = st.peek() {
+        st.next();
     }
 }
 
-/// Applies one parser, discards the result, and returns the second parser's results if the first
-/// one succeeded. To skip the input consumed by several parsers, use a `Sequence` combinators as
-/// `A`.
-pub struct Then<A: Parser, B: Parser> {
-    a: A,
-    b: B,
+/// IndentedBlock parses a block of items (each parsed by `P`) that all start at the same column,
+/// as used by indentation-sensitive ("offside rule") languages like Python or YAML. Spaces, tabs
+/// and newlines before each item are skipped; a tab counts as a single column. The block ends
+/// before the first item that starts in a different column or fails to parse.
+///
+/// While parsing the items, the block's column is available as `ParseState::indentation()`, and
+/// `SameIndent` can be used to check against it.
+///
+/// Use `indented_block()` for a block that must be indented further than the enclosing block, and
+/// `aligned()` for a block without this requirement (e.g. at the top level).
+pub struct IndentedBlock<P: Parser> {
+    inner: P,
+    deeper: bool,
 }
 
-impl<A: Parser, B: Parser> Then<A, B> {
-    pub fn new(first: A, second: B) -> Then<A, B> {
-        Then {
-            a: first,
-            b: second,
+impl<P: Parser> IndentedBlock<P> {
+    /// Create a block that needs to be indented further than the enclosing block.
+    pub fn new(p: P) -> IndentedBlock<P> {
+        IndentedBlock {
+            inner: p,
+            deeper: true,
+        }
+    }
+    /// Create a block that may start at any column.
+    pub fn new_aligned(p: P) -> IndentedBlock<P> {
+        IndentedBlock {
+            inner: p,
+            deeper: false,
         }
     }
 }
 
-impl<A: Parser, B: Parser> Parser for Then<A, B> {
-    type Result = B::Result;
+/// Parse a block of items indented further than the enclosing block. See `IndentedBlock`.
+pub fn indented_block<P: Parser>(p: P) -> IndentedBlock<P> {
+    IndentedBlock::new(p)
+}
+
+/// Parse a block of items aligned at the same column. See `IndentedBlock`.
+pub fn aligned<P: Parser>(p: P) -> IndentedBlock<P> {
+    IndentedBlock::new_aligned(p)
+}
+
+impl<R, P: Parser<Result = R>> Parser for IndentedBlock<P> {
+    type Result = Vec<R>;
     fn parse(
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        match self.a.parse(st) {
-            Ok(_) => (),
-            Err(e) => return Err(e),
+        let hold = st.hold();
+        skip_layout(st);
+        let column = st.column();
+        if st.peek().is_none() || (self.deeper && column <= st.indentation()) {
+            let ix = st.index();
+            st.reset(hold);
+            return Err(ParseError::Fail("expected indented block", ix));
+        }
+        st.push_indentation(column);
+        let mut v = vec![];
+        loop {
+            let item = st.hold();
+            let start = st.index();
+            if !v.is_empty() {
+                skip_layout(st);
+                if st.peek().is_none() || st.column() != column {
+                    st.reset(item);
+                    break;
+                }
+            }
+            match self.inner.parse(st) {
+                Ok(r) => {
+                    v.push(r);
+                    // Stop if the item didn't consume anything; it would match forever.
+                    if st.index() == start {
+                        st.release(item);
+                        break;
+                    }
+                    st.release(item);
+                }
+                Err(e) => {
+                    st.reset(item);
+                    if v.is_empty() {
+                        st.pop_indentation();
+                        st.reset(hold);
+                        return Err(e);
+                    }
+                    break;
+                }
+            }
         }
-        self.b.parse(st)
+        st.pop_indentation();
+        st.release(hold);
+        Ok(v)
     }
 }
 
-/// Lazy is a helper for a typical situation where you have an `Alternative` or a `Sequence` and
-/// don't want to construct an expensive parser every time just in order for it to be dropped
-/// without having parsed anything. For example:
-///
-/// ```ignore
-/// // Let's say dict is really expensive! the first ones not as much
-/// let mut p = Alternative::new((number(), string(), atom(), dict()));
-/// ```
-///
-/// Then you can wrap the `dict` parser constructor in a `Lazy` parser. Then it will only be
-/// constructed if the `Alternative` actually needs a `dict` parser:
-///
-/// ```ignore
-/// let mut p = Alternative::new((number(), string(), atom(), Lazy::new(dict)));
-/// ```
-///
-/// Constructing a `Lazy` combinator is in comparison quite cheap, as it only involves copying a
-/// function pointer. `Lazy` also caches the result of the function, meaning it will be called at
-/// most once.
-pub struct Lazy<P, F: FnMut() -> P>(F, Option<P>);
+/// SameIndent only succeeds if the input is at the column of the innermost `IndentedBlock`, and
+/// then parses `P`. It is useful for items that are not directly parsed by an `IndentedBlock`, for
+/// example the `else` branch following an `if` block.
+pub struct SameIndent<P: Parser>(P);
 
-impl<R, P: Parser<Result = R>, F: FnMut() -> P> Lazy<P, F> {
-    /// Create a new instance of `Lazy`:
-    ///
-    /// ```ignore
-    /// let l = Lazy::new(|| some_expensive_function());
-    /// ```
-    pub fn new(f: F) -> Lazy<P, F> {
-        Lazy(f, None)
+impl<P: Parser> SameIndent<P> {
+    pub fn new(p: P) -> SameIndent<P> {
+        SameIndent(p)
     }
 }
 
-impl<R, P: Parser<Result = R>, F: FnMut() -> P> Parser for Lazy<P, F> {
+/// Parse `p` only if the input is at the column of the innermost indented block. See
+/// `SameIndent`.
+pub fn same_indent<P: Parser>(p: P) -> SameIndent<P> {
+    SameIndent::new(p)
+}
+
+impl<R, P: Parser<Result = R>> Parser for SameIndent<P> {
     type Result = R;
     fn parse(
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        if self.1.is_none() {
-            self.1 = Some((self.0)());
+        if st.column() != st.indentation() {
+            return Err(ParseError::Fail("wrong indentation", st.index()));
         }
-        self.1.as_mut().unwrap().parse(st)
+        self.0.parse(st)
     }
 }
 
@@ -435,9 +2199,13 @@ mod tests {
     use crate::parser::Parser;
     use crate::primitives::*;
 
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
     #[test]
     fn test_pair() {
-        let mut p = Sequence::new((Int64::new(), StringParser::new(" aba".to_string())));
+        let mut p = Sequence::new((Int64::new(), StringParser::new(" aba")));
         let mut ps = ParseState::new("123 aba");
         assert_eq!(Ok((123, " aba".to_string())), p.parse(&mut ps));
     }
@@ -464,6 +2232,419 @@ mod tests {
         );
     }
 
+    #[derive(Clone, Default)]
+    struct Symbols(Vec<String>);
+
+    #[test]
+    fn test_user_state() {
+        let ident = || string_of("abcdefghijklmnopqrstuvwxyz", RepeatSpec::Min(1));
+        let mut decl =
+            StringParser::new("let ")
+                .then(ident())
+                .apply_with_state(|name, syms: &mut Symbols| {
+                    syms.0.push(name);
+                    Ok(())
+                });
+        let mut usage = ident().apply_with_state(|name, syms: &mut Symbols| {
+            if syms.0.contains(&name) {
+                Ok(name)
+            } else {
+                Err(execerr("undeclared"))
+            }
+        });
+
+        let mut ps = ParseState::new("x");
+        assert!(usage.parse(&mut ps).is_err());
+
+        let mut ps = ParseState::new("let x;x;y");
+        ps.set_user_state(Symbols::default(), false);
+        assert_eq!(Ok(()), decl.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+        assert_eq!(Ok("x".to_string()), usage.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+        assert!(usage.parse(&mut ps).is_err());
+        assert_eq!(
+            vec!["x".to_string()],
+            ps.take_user_state::<Symbols>().unwrap().0
+        );
+    }
+
+    #[test]
+    fn test_user_state_rollback() {
+        let count = || {
+            StringParser::new("a").apply_with_state(|s, n: &mut usize| {
+                *n += 1;
+                Ok(s)
+            })
+        };
+        for &rollback in [true, false].iter() {
+            let mut p = Alternative::new((
+                Sequence::new((count(), count(), StringParser::new("c"))).apply(|_| Ok(1)),
+                Sequence::new((count(), count())).apply(|_| Ok(2)),
+            ));
+            let mut ps = ParseState::new("aab");
+            ps.set_user_state(0_usize, rollback);
+            assert_eq!(Ok(2), p.parse(&mut ps));
+            let want = if rollback { 2 } else { 4 };
+            assert_eq!(Some(&want), ps.user_state::<usize>());
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Node(String, Vec<Node>);
+
+    struct NodeParser;
+
+    impl Parser for NodeParser {
+        type Result = Node;
+        fn parse(
+            &mut self,
+            st: &mut ParseState<impl Iterator<Item = char>>,
+        ) -> ParseResult<Self::Result> {
+            let name = string_of("abcdefghijklmnopqrstuvwxyz", RepeatSpec::Min(1));
+            // A `:` may be followed by an indented block of children.
+            let children = StringParser::new(":").then(Maybe::new(indented_block(NodeParser)));
+            Sequence::new((name, Maybe::new(children)))
+                .apply(|(n, c)| Ok(Node(n, c.flatten().unwrap_or_default())))
+                .parse(st)
+        }
+    }
+
+    #[test]
+    fn test_indented_block() {
+        let leaf = |n: &str| Node(n.to_string(), vec![]);
+        let mut ps = ParseState::new("a:\n  b\n  c:\n      d\n      e\n  f\ng:\n\n h\n  i\n");
+        assert_eq!(
+            Ok(vec![
+                Node(
+                    "a".to_string(),
+                    vec![
+                        leaf("b"),
+                        Node("c".to_string(), vec![leaf("d"), leaf("e")]),
+                        leaf("f")
+                    ]
+                ),
+                Node("g".to_string(), vec![leaf("h")]),
+            ]),
+            aligned(NodeParser).parse(&mut ps)
+        );
+        // The over-indented "i" ends the top-level block.
+        assert_eq!(
+            Ok("\n  i\n".to_string()),
+            string_of(" \ni", RepeatSpec::Any).parse(&mut ps)
+        );
+
+        // Children must be indented further than their parent.
+        let mut ps = ParseState::new("  a:\n  b");
+        assert_eq!(
+            Ok(vec![leaf("a"), leaf("b")]),
+            aligned(NodeParser).parse(&mut ps)
+        );
+    }
+
+    #[test]
+    fn test_same_indent() {
+        let mut ps = ParseState::new("x\n y");
+        let item = same_indent(OneOf::new("xyz"));
+        assert_eq!(Ok(vec!['x']), aligned(item).parse(&mut ps));
+        assert_eq!((2, 1), (ps.column(), ps.indentation()));
+        assert!(same_indent(StringParser::new("\n")).parse(&mut ps).is_err());
+
+        // Outside of any block, items must start in the first column.
+        let mut ps = ParseState::new("x\n y");
+        assert_eq!(Ok('x'), same_indent(OneOf::new("xyz")).parse(&mut ps));
+        assert_eq!(Ok('\n'), OneOf::new("\n").parse(&mut ps));
+        assert_eq!(Ok(' '), same_indent(OneOf::new(" ")).parse(&mut ps));
+        assert_eq!(
+            Err(ParseError::Fail("wrong indentation", 3)),
+            same_indent(OneOf::new("y")).parse(&mut ps)
+        );
+    }
+
+    /// Counts how often it is invoked.
+    struct Counting<P>(P, Rc<Cell<usize>>);
+
+    impl<P: Parser> Parser for Counting<P> {
+        type Result = P::Result;
+        fn parse(
+            &mut self,
+            st: &mut ParseState<impl Iterator<Item = char>>,
+        ) -> ParseResult<Self::Result> {
+            self.1.set(self.1.get() + 1);
+            self.0.parse(st)
+        }
+    }
+
+    #[test]
+    fn test_memo() {
+        let calls = Rc::new(Cell::new(0));
+        let number = || Memo::named("number", Counting(Int32::new(), calls.clone()));
+        let mut p = Alternative::new((
+            Sequence::new((number(), StringParser::new("+"), Int32::new()))
+                .apply(|(a, _, b)| Ok(a + b)),
+            Sequence::new((number(), StringParser::new("-"), Int32::new()))
+                .apply(|(a, _, b)| Ok(a - b)),
+            number(),
+        ));
+        let mut ps = ParseState::new("12-3 5");
+        assert_eq!(Ok(9), p.parse(&mut ps));
+        assert_eq!(1, calls.get());
+        assert!(StringParser::new(" ").parse(&mut ps).is_ok());
+        assert_eq!(Ok(5), p.parse(&mut ps));
+        assert_eq!(2, calls.get());
+
+        // Errors are cached as well.
+        let mut p = Memo::new(Counting(Int32::new(), calls.clone()));
+        let mut ps = ParseState::new("x");
+        assert!(p.parse(&mut ps).is_err());
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(3, calls.get());
+        assert_eq!(0, ps.index());
+    }
+
+    #[test]
+    fn test_memo_include() {
+        // A cached zero-width failure at the include position.
+        let mut p = Memo::new(Int32::new());
+        let mut ps = ParseState::new("x");
+        assert!(p.parse(&mut ps).is_err());
+        ps.include("inc", "5");
+        assert_eq!(Ok(5), p.parse(&mut ps));
+
+        // A cached result that ended at the include position due to lookahead.
+        let mut ps = ParseState::new("12;");
+        let hold = ps.hold();
+        assert_eq!(Ok(12), p.parse(&mut ps));
+        ps.include("inc", "3");
+        ps.reset(hold);
+        assert_eq!(Ok(123), p.parse(&mut ps));
+    }
+
+    /// expr := expr '+' term | expr '-' term | term
+    /// term := term '*' int | int
+    struct Expr;
+    struct Term;
+
+    impl Parser for Expr {
+        type Result = i64;
+        fn parse(
+            &mut self,
+            st: &mut ParseState<impl Iterator<Item = char>>,
+        ) -> ParseResult<Self::Result> {
+            let add =
+                Sequence::new((Expr, StringParser::new("+"), Term)).apply(|(a, _, b)| Ok(a + b));
+            let sub =
+                Sequence::new((Expr, StringParser::new("-"), Term)).apply(|(a, _, b)| Ok(a - b));
+            LeftRec::new("expr", Alternative::new((add, sub, Term))).parse(st)
+        }
+    }
+
+    impl Parser for Term {
+        type Result = i64;
+        fn parse(
+            &mut self,
+            st: &mut ParseState<impl Iterator<Item = char>>,
+        ) -> ParseResult<Self::Result> {
+            let mul = Sequence::new((Term, StringParser::new("*"), Int64::new()))
+                .apply(|(a, _, b)| Ok(a * b));
+            LeftRec::new("term", Alternative::new((mul, Int64::new()))).parse(st)
+        }
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        let mut ps = ParseState::new("10-2-3 1+2*3*4-5 x");
+        assert_eq!(Ok(5), Expr.parse(&mut ps));
+        assert!(StringParser::new(" ").parse(&mut ps).is_ok());
+        assert_eq!(Ok(20), Expr.parse(&mut ps));
+        assert!(StringParser::new(" ").parse(&mut ps).is_ok());
+        let ix = ps.index();
+        assert!(Expr.parse(&mut ps).is_err());
+        assert_eq!(ix, ps.index());
+    }
+
+    #[test]
+    fn test_permutation() {
+        let attr = |name: &'static str| {
+            StringParser::new(name)
+                .then(StringParser::new("="))
+                .then(Int32::new())
+        };
+        let sep = || Maybe::new(StringParser::new(","));
+        let mut p = Permutation::new((
+            Sequence::new((attr("width"), sep())).apply(|(i, _)| Ok(i)),
+            Sequence::new((attr("height"), sep())).apply(|(i, _)| Ok(i)),
+            Optional::new(Sequence::new((attr("depth"), sep())).apply(|(i, _)| Ok(i))),
+        ));
+        let mut ps = ParseState::new("height=2,width=1;width=3,height=4,depth=5;depth=6,width=7");
+        assert_eq!(Ok((1, 2, None)), p.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+        assert_eq!(Ok((3, 4, Some(5))), p.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+        let ix = ps.index();
+        assert_eq!(
+            Err(ParseError::MissingElement(1, ix + 15)),
+            p.parse(&mut ps)
+        );
+        assert_eq!(ix, ps.index());
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut p = Sequence::new((Uint8::new(), StringParser::new(":")))
+            .and_then(|(n, _)| string_none_of("", RepeatSpec::Between(n as usize, n as usize)));
+        let mut ps = ParseState::new("3:abcd5:ef");
+        assert_eq!(Ok("abc".to_string()), p.parse(&mut ps));
+        assert!(StringParser::new("d").parse(&mut ps).is_ok());
+        let ix = ps.index();
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(ix, ps.index());
+
+        // Choose the closing delimiter depending on the opening one.
+        let mut p = OneOf::new("([{")
+            .and_then(|c| {
+                let close = match c {
+                    '(' => ")",
+                    '[' => "]",
+                    _ => "}",
+                };
+                Sequence::new((Int32::new(), StringParser::new(close)))
+            })
+            .apply(|(i, _)| Ok(i));
+        let mut ps = ParseState::new("[1](2}");
+        assert_eq!(Ok(1), p.parse(&mut ps));
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(3, ps.index());
+    }
+
+    #[test]
+    fn test_many_till() {
+        let mut p = ManyTill::new(
+            Sequence::new((Int32::new(), Maybe::new(StringParser::new(",")))).apply(|(i, _)| Ok(i)),
+            StringParser::new(";"),
+        );
+        let mut ps = ParseState::new("1,2,3;;4,x;");
+        assert_eq!(Ok((vec![1, 2, 3], ";".to_string())), p.parse(&mut ps));
+        assert_eq!(Ok((vec![], ";".to_string())), p.parse(&mut ps));
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(7, ps.index());
+    }
+
+    /// All strings of up to five chars from a small alphabet.
+    fn atomicity_inputs() -> Vec<String> {
+        let mut all = vec![String::new()];
+        let mut last = all.clone();
+        for _ in 0..5 {
+            last = last
+                .iter()
+                .flat_map(|s| "ab1 ,".chars().map(move |c| format!("{}{}", s, c)))
+                .collect();
+            all.extend(last.iter().cloned());
+        }
+        all
+    }
+
+    /// Check that `p` either succeeds, or fails without consuming input, for all inputs from
+    /// `atomicity_inputs()`. Combinators are only required to be atomic if their sub-parsers are,
+    /// so the tests below combine atomic primitives in ways that consume input before failing.
+    fn assert_atomic<P: Parser>(name: &str, mut p: P) {
+        for input in atomicity_inputs() {
+            let mut ps = ParseState::new(&input);
+            ps.set_user_state(0_usize, false);
+            if p.parse(&mut ps).is_err() {
+                assert_eq!(0, ps.index(), "{} is not atomic for {:?}", name, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_atomicity() {
+        let ab = || StringParser::new("ab");
+        let num = || Int32::new();
+        fn fail<T>(_: T) -> ParseResult<()> {
+            Err(execerr("fail"))
+        }
+        assert_atomic("Transform", ab().apply(Ok));
+        assert_atomic("Transform (failing)", ab().apply(fail));
+        assert_atomic("Transform (failing)", num().apply(fail));
+        assert_atomic(
+            "TransformWithState",
+            num().apply_with_state(|i, _: &mut usize| fail(i)),
+        );
+        assert_atomic("Then", num().then(ab()));
+        assert_atomic("Then", ab().then(num()));
+        assert_atomic("Bind", num().and_then(|_| ab()));
+        assert_atomic("Terminated", terminated(num(), ab()));
+        assert_atomic("Terminated", terminated(ab(), num()));
+        assert_atomic(
+            "SkippingSequence",
+            SkippingSequence::new((Skip::new(num()), ab(), Skip::new(ab()))),
+        );
+        assert_atomic("Ignore", Ignore::new(num().then(ab())));
+        assert_atomic("Lazy", Lazy::new(|| num().then(ab())));
+        assert_atomic("Memo", Memo::new(num().then(ab())));
+        assert_atomic("LeftRec", LeftRec::new("atomicity", num().then(ab())));
+        assert_atomic("SameIndent", same_indent(num().then(ab())));
+        assert_atomic("Alternative", Alternative::new((ab(), num().then(ab()))));
+        assert_atomic("OrEither", num().then(ab()).or_either(ab()));
+        assert_atomic("Choice", Choice::new((ab(), num().then(ab()), num())));
+        assert_atomic("Sequence", Sequence::new((num(), ab(), num())));
+        assert_atomic("Permutation", Permutation::new((num(), ab())));
+        assert_atomic("Repeat", Repeat::new(ab(), RepeatSpec::Min(2)));
+        assert_atomic("Repeat", Repeat::new(num().then(ab()), RepeatSpec::Min(2)));
+        assert_atomic(
+            "Repeat",
+            Repeat::new(num().then(ab()), RepeatSpec::Exactly(1)),
+        );
+        assert_atomic(
+            "Fold",
+            Repeat::new(ab(), RepeatSpec::Min(1)).fold(0, |n, _| n + 1),
+        );
+        assert_atomic(
+            "Collect",
+            Repeat::new(ab(), RepeatSpec::Min(1)).collect::<String>(),
+        );
+        assert_atomic("Count", count::<2, _>(ab()));
+        assert_atomic("ManyTill", ManyTill::new(ab(), StringParser::new(",")));
+        assert_atomic("ManyTill", ManyTill::new(num(), ab()));
+        assert_atomic(
+            "ManyTill",
+            ManyTill::new(num().then(ab()), StringParser::new(",")),
+        );
+        assert_atomic("IndentedBlock", indented_block(ab()));
+        assert_atomic("IndentedBlock", aligned(ab()));
+
+        // Combinators that always succeed must not consume input for failing sub-parsers either.
+        for input in atomicity_inputs() {
+            let mut ps = ParseState::new(&input);
+            let ok = |p: ParseResult<Option<String>>| p.unwrap().is_some();
+            let ix = ps.index();
+            if !ok(Maybe::new(num().then(ab())).parse(&mut ps)) {
+                assert_eq!(ix, ps.index(), "Maybe is not atomic for {:?}", input);
+            }
+            let ix = ps.index();
+            if !ok(PartialSequence::new((num().then(ab()), ab()))
+                .parse(&mut ps)
+                .map(|r| r.0))
+            {
+                assert_eq!(
+                    ix,
+                    ps.index(),
+                    "PartialSequence is not atomic for {:?}",
+                    input
+                );
+            }
+            let ix = ps.index();
+            if Repeat::new(num().then(ab()), RepeatSpec::Any)
+                .parse(&mut ps)
+                .unwrap()
+                .is_empty()
+            {
+                assert_eq!(ix, ps.index(), "Repeat is not atomic for {:?}", input);
+            }
+        }
+    }
+
     #[test]
     fn test_then() {
         let mut ps = ParseState::new("abcdef 123");
@@ -474,6 +2655,60 @@ mod tests {
         assert_eq!(Ok(123), p.parse(&mut ps));
     }
 
+    #[test]
+    fn test_preceded_terminated_delimited() {
+        let mut ps = ParseState::new("-12;(34)");
+        assert_eq!(
+            Ok(12),
+            preceded(StringParser::new("-"), Int32::new()).parse(&mut ps)
+        );
+        assert!(terminated(StringParser::new(";"), Int32::new())
+            .parse(&mut ps)
+            .is_err());
+        assert_eq!(3, ps.index());
+        assert_eq!(
+            Ok(";".to_string()),
+            terminated(StringParser::new(";"), StringParser::new("(")).parse(&mut ps)
+        );
+        assert_eq!(
+            Ok(34),
+            terminated(Int32::new(), StringParser::new(")")).parse(&mut ps)
+        );
+
+        let mut ps = ParseState::new("( 1 )( 2 ]");
+        let mut p = delimited(
+            StringParser::new("("),
+            terminated(preceded(whitespace(), Int32::new()), whitespace()),
+            StringParser::new(")"),
+        );
+        assert_eq!(Ok(1), p.parse(&mut ps));
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(5, ps.index());
+    }
+
+    #[test]
+    fn test_skipping_sequence() {
+        let mut ps = ParseState::new("a = 1; b=2;");
+        let mut p = SkippingSequence::new((
+            OneOf::new("ab"),
+            Skip::new(whitespace()),
+            Skip::new(StringParser::new("=")),
+            Skip::new(whitespace()),
+            Int32::new(),
+            Skip::new(StringParser::new(";")),
+            Skip::new(whitespace()),
+        ));
+        assert_eq!(Ok(('a', 1)), p.parse(&mut ps));
+        assert_eq!(Ok(('b', 2)), p.parse(&mut ps));
+
+        // Unmarked parsers with () results are kept.
+        let mut ps = ParseState::new(" x");
+        let mut p = SkippingSequence::new((whitespace(), StringParser::new("x")));
+        assert_eq!(Ok(((), "x".to_string())), p.parse(&mut ps));
+        let mut p = SkippingSequence::new((Skip::new(Int32::new()), Skip::new(whitespace())));
+        assert_eq!(Ok(()), p.parse(&mut ParseState::new("1 ")));
+    }
+
     #[test]
     fn test_alternative() {
         let mut p = Alternative::new((
@@ -488,6 +2723,133 @@ mod tests {
         assert_eq!(Ok("34".to_string()), p.parse(&mut ps));
     }
 
+    #[test]
+    fn test_large_tuples() {
+        let d = || OneOf::new("0123456789");
+        let mut p = Sequence::new((
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+        ));
+        let mut ps = ParseState::new("0123456789abcdef");
+        assert!(p.parse(&mut ps).is_err());
+        assert_eq!(0, ps.index());
+
+        let mut ps = ParseState::new("0123456789012345");
+        let r = p.parse(&mut ps).unwrap();
+        assert_eq!(('0', '9', '5'), (r.0, r.9, r.15));
+
+        let mut p = PartialSequence::new((
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+            d(),
+        ));
+        let mut ps = ParseState::new("0123456789abcdef");
+        let r = p.parse(&mut ps).unwrap();
+        assert_eq!((Some('9'), None, None), (r.9, r.10, r.15));
+        assert_eq!(10, ps.index());
+
+        let s = |c| StringParser::new(c);
+        let mut p = Alternative::new((
+            s("a"),
+            s("b"),
+            s("c"),
+            s("d"),
+            s("e"),
+            s("f"),
+            s("g"),
+            s("h"),
+            s("i"),
+            s("j"),
+            s("k"),
+            s("l"),
+            s("m"),
+            s("n"),
+            s("o"),
+            s("p"),
+        ));
+        let mut ps = ParseState::new("p");
+        assert_eq!(Ok("p".to_string()), p.parse(&mut ps));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let d = || OneOf::new("0123456789");
+        let mut p = Sequence::new((
+            Sequence::new((d(), d(), d(), d(), d(), d(), d(), d(), d(), d())),
+            Sequence::new((d(), d(), d(), d(), d(), d(), d(), d(), d(), d())),
+        ))
+        .flatten()
+        .apply(|r| Ok((r.0, r.10, r.19)));
+        let mut ps = ParseState::new("01234567890123456789");
+        assert_eq!(Ok(('0', '0', '9')), p.parse(&mut ps));
+
+        assert_eq!((1, 2, 3), ((1,), (2, 3)).flatten());
+        assert_eq!((1, 2, 3), ((1, 2), (3,)).flatten());
+    }
+
+    #[test]
+    fn test_or_either() {
+        let mut p = Int64::new().or_either(StringParser::new("x"));
+        let mut ps = ParseState::new("12x?");
+        assert_eq!(Ok(Either::Left(12)), p.parse(&mut ps));
+        assert_eq!(Ok(Either::Right("x".to_string())), p.parse(&mut ps));
+        assert_eq!(
+            Err(ParseError::Fail("no alternative matched", 3)),
+            p.parse(&mut ps)
+        );
+    }
+
+    #[test]
+    fn test_choice() {
+        let mut p = Repeat::new(
+            Choice::new((
+                Int64::new(),
+                StringParser::new("x"),
+                OneOf::new("yz"),
+                whitespace(),
+            )),
+            RepeatSpec::Any,
+        );
+        let mut ps = ParseState::new("1x y2");
+        assert_eq!(
+            Ok(vec![
+                OneOf4::A(1),
+                OneOf4::B("x".to_string()),
+                OneOf4::D(()),
+                OneOf4::C('y'),
+                OneOf4::A(2)
+            ]),
+            p.parse(&mut ps)
+        );
+    }
+
     #[test]
     fn test_repeat() {
         let mut ps = ParseState::new("aaa aaa aaaa aaaa");
@@ -525,6 +2887,98 @@ mod tests {
         assert!(StringParser::new("a").parse(&mut ps).is_ok());
     }
 
+    #[test]
+    fn test_fold_collect() {
+        let mut ps = ParseState::new("1,2,3,;a=1,b=2,;");
+        let num = || Sequence::new((Int32::new(), StringParser::new(","))).apply(|(i, _)| Ok(i));
+        let mut sum = Repeat::new(num(), RepeatSpec::Min(1)).fold(0, |acc, i| acc + i);
+        assert_eq!(Ok(6), sum.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+
+        let entry = Sequence::new((OneOf::new("ab"), StringParser::new("="), num()))
+            .apply(|(k, _, v)| Ok((k, v)));
+        let mut map = Repeat::new(entry, RepeatSpec::Any).collect::<HashMap<char, i32>>();
+        let want: HashMap<char, i32> = [('a', 1), ('b', 2)].iter().cloned().collect();
+        assert_eq!(Ok(want), map.parse(&mut ps));
+        assert!(StringParser::new(";").parse(&mut ps).is_ok());
+        // The fold starts again from init on every invocation.
+        assert!(sum.parse(&mut ParseState::new("1")).is_err());
+        assert_eq!(Ok(4), sum.parse(&mut ParseState::new("4,")));
+    }
+
+    #[test]
+    fn test_count() {
+        let mut ps = ParseState::new("aaaa\\u00e9192.168.0.1");
+        assert_eq!(
+            Ok(3),
+            Repeat::new(StringParser::new("a"), RepeatSpec::Exactly(3))
+                .parse(&mut ps)
+                .map(|v| v.len())
+        );
+        assert!(Repeat::new(StringParser::new("a"), RepeatSpec::Exactly(2))
+            .parse(&mut ps)
+            .is_err());
+        assert!(StringParser::new("a").parse(&mut ps).is_ok());
+
+        let mut escape = StringParser::new("\\u")
+            .then(count::<4, _>(OneOf::new("0123456789abcdefABCDEF")))
+            .apply(|digits| {
+                let s: String = digits.iter().collect();
+                u32::from_str_radix(&s, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| execerr("invalid code point"))
+            });
+        assert_eq!(Ok('é'), escape.parse(&mut ps));
+
+        let octet = || {
+            Sequence::new((Uint8::new(), Maybe::new(StringParser::new(".")))).apply(|(o, _)| Ok(o))
+        };
+        assert_eq!(Ok([192, 168, 0, 1]), count::<4, _>(octet()).parse(&mut ps));
+        let mut ps = ParseState::new("10.0.1");
+        assert!(count::<4, _>(octet()).parse(&mut ps).is_err());
+        assert_eq!(0, ps.index());
+    }
+
+    #[test]
+    fn test_repeat_no_progress() {
+        let mut ps = ParseState::new("aab");
+        let mut p = Repeat::new(Maybe::new(StringParser::new("a")), RepeatSpec::Any);
+        assert_eq!(
+            Ok(vec![Some("a".to_string()), Some("a".to_string())]),
+            p.parse(&mut ps)
+        );
+        assert_eq!(Ok(vec![]), p.parse(&mut ps));
+        let mut p = Repeat::new(Maybe::new(StringParser::new("a")), RepeatSpec::Min(2));
+        assert_eq!(Ok(vec![None, None]), p.parse(&mut ps));
+        assert_eq!(2, ps.index());
+
+        assert_eq!(
+            Ok(vec![]),
+            Repeat::new(whitespace(), RepeatSpec::Any).parse(&mut ps)
+        );
+        assert_eq!(
+            Ok(3),
+            Repeat::new(Nothing, RepeatSpec::Exactly(3))
+                .parse(&mut ps)
+                .map(|v| v.len())
+        );
+        assert_eq!(
+            Ok(0),
+            Repeat::new(Nothing, RepeatSpec::Any)
+                .fold(0, |n, ()| n + 1)
+                .parse(&mut ps)
+        );
+
+        let mut p = ManyTill::new(Maybe::new(StringParser::new("a")), StringParser::new("c"));
+        let mut ps = ParseState::new("ab");
+        assert_eq!(
+            Err(ParseError::Fail("no progress in repetition", 1)),
+            p.parse(&mut ps)
+        );
+        assert_eq!(0, ps.index());
+    }
+
     #[test]
     fn test_partial_sequence() {
         let mut p =
@@ -547,6 +3001,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unreachable_code)]
     fn test_lazy() {
         let mut ps = ParseState::new("123");
         let mut p = Alternative::new((
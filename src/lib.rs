@@ -53,9 +53,11 @@ extern crate time_test;
 pub mod combinators;
 pub mod parser;
 pub mod primitives;
+mod source;
 mod state;
 
 pub use combinators::{Alternative, Maybe, PartialSequence, Repeat, Sequence, Then, Transform};
 pub use parser::{execerr, ParseResult, Parser};
-pub use primitives::{float, string_none_of, string_of, whitespace, Int, StringParser};
+pub use primitives::{float, string_none_of, string_of, whitespace, Float, Int, StringParser};
+pub use source::Location;
 pub use state::ParseState;
@@ -1,9 +1,9 @@
 use std::fmt;
 
-use crate::combinators::{Then, Transform};
+use crate::combinators::{Bind, Flat, Flatten, OrEither, Then, Transform, TransformWithState};
 use crate::state::ParseState;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ParseError {
     /// Input is over.
     EOF,
@@ -14,6 +14,8 @@ pub enum ParseError {
     /// ExecFail is an error that occurred while executing "user code", e.g. during a Transform
     /// parser.
     ExecFail(String),
+    /// A required element of a `Permutation` (given by its index in the tuple) was not found.
+    MissingElement(usize, usize),
 }
 
 /// This function returns an error for returning from a function called by a `Transform` parser.
@@ -21,6 +23,19 @@ pub fn execerr<S: AsRef<str>>(s: S) -> ParseError {
     ParseError::ExecFail(s.as_ref().to_string())
 }
 
+impl ParseError {
+    /// Return the global index at which the error occurred, if known. Use
+    /// `ParseState::location()` to translate it into a source location.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ParseError::Fail(_, pos)
+            | ParseError::TransformFail(_, pos, _)
+            | ParseError::MissingElement(_, pos) => Some(*pos),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -30,6 +45,9 @@ impl fmt::Display for ParseError {
                 write!(f, "Transform fail: {} at {} due to ", s, pos).and_then(|()| inner.fmt(f))
             }
             ParseError::ExecFail(s) => write!(f, "Logic error: {}", s),
+            ParseError::MissingElement(i, pos) => {
+                write!(f, "Parse fail: missing element {} at {}", i, pos)
+            }
         }
     }
 }
@@ -77,6 +95,49 @@ pub trait Parser {
         Transform::new(self, f)
     }
 
+    /// `apply_with_state` transforms the result of this parser using a function that also has
+    /// access to the user state stored in the `ParseState`.
+    fn apply_with_state<R2, S, F: Fn(Self::Result, &mut S) -> ParseResult<R2>>(
+        self,
+        f: F,
+    ) -> TransformWithState<Self::Result, R2, S, Self, F>
+    where
+        Self: std::marker::Sized,
+    {
+        TransformWithState::new(self, f)
+    }
+
+    /// `and_then` runs this parser, and then the parser returned by `f` for its result, returning
+    /// the latter's result. This allows choosing the next parser depending on the input parsed so
+    /// far, for example reading a length and then exactly that many characters.
+    fn and_then<P: Parser, F: Fn(Self::Result) -> P>(self, f: F) -> Bind<Self, P, F>
+    where
+        Self: std::marker::Sized,
+    {
+        Bind::new(self, f)
+    }
+
+    /// `or_either` attempts this parser, and if it fails, parser `p`. The parsers may have
+    /// different result types; the result tells which one succeeded. See also `Choice` for more
+    /// than two parsers.
+    fn or_either<P: Parser>(self, p: P) -> OrEither<Self, P>
+    where
+        Self: std::marker::Sized,
+    {
+        OrEither::new(self, p)
+    }
+
+    /// `flatten` turns a result consisting of a pair of tuples, e.g. `((a, b), (c,))`, into a
+    /// single tuple `(a, b, c)`. This is useful for sequences of more than 16 elements, which have
+    /// to be split into several nested `Sequence`s.
+    fn flatten(self) -> Flat<Self>
+    where
+        Self: std::marker::Sized,
+        Self::Result: Flatten,
+    {
+        Flat::new(self)
+    }
+
     /// `then` attempts to parse input, and if it succeeds, executes parser `p`, only returning
     /// `p`'s result. This is useful for chaining parsers of which the results are not need.
     fn then<R2, P: Parser<Result = R2>>(self, p: P) -> Then<Self, P>
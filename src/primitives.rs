@@ -1,19 +1,56 @@
-use crate::combinators::{Ignore, Maybe, Repeat, RepeatSpec, Sequence};
+use crate::combinators::{terminated, Ignore, Maybe, Repeat, RepeatSpec, Sequence, Terminated};
 use crate::parser::{execerr, ParseError, ParseResult, Parser};
 use crate::state::ParseState;
 
+use unicode_xid::UnicodeXID;
+
 use std::collections::HashSet;
-use std::error::Error;
-use std::iter::FromIterator;
-use std::str::{self, FromStr};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::num::IntErrorKind;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// CaseMode determines how a `StringParser` compares characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CaseMode {
+    Exact,
+    Ascii,
+    Unicode,
+}
+
+/// Map a character for case-insensitive comparison. Rust's standard library has no Unicode case
+/// folding tables, so this approximates simple case folding by the lower case mapping, where it is
+/// a single character. This maps e.g. `K`, `k` and `K` (Kelvin sign) to the same character, but
+/// is stricter than case folding in some cases: `ς` does not match `σ`, and `İ` only matches
+/// itself. It never maps characters that aren't case variants of each other to the same one (`ı`
+/// does not match `i`).
+fn fold_case(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
 
-/// StringParser consumes a fixed string.
+/// StringParser consumes a fixed string. It can optionally ignore case, in which case the result
+/// is the string as it appeared in the input.
 #[derive(Clone, Debug, PartialEq)]
-pub struct StringParser(String);
+pub struct StringParser(String, CaseMode);
 
 impl StringParser {
     pub fn new<S: AsRef<str>>(s: S) -> StringParser {
-        StringParser(s.as_ref().to_owned())
+        StringParser(s.as_ref().to_owned(), CaseMode::Exact)
+    }
+    /// Create a StringParser ignoring the case of ASCII letters.
+    pub fn new_ascii_case_insensitive<S: AsRef<str>>(s: S) -> StringParser {
+        StringParser(s.as_ref().to_owned(), CaseMode::Ascii)
+    }
+    /// Create a StringParser ignoring case according to the Unicode lower case mapping, where every
+    /// character matches exactly one character. This approximates Unicode simple case folding.
+    pub fn new_case_insensitive<S: AsRef<str>>(s: S) -> StringParser {
+        StringParser(s.as_ref().to_owned(), CaseMode::Unicode)
     }
 }
 
@@ -23,36 +60,216 @@ impl Parser for StringParser {
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        let mut cs = self.0.chars();
-        let expect = self.0.len();
-        let mut have = 0;
+        let mode = self.1;
+        let mut matched = String::new();
         let hold = st.hold();
-        loop {
-            let (next, pk) = (cs.next(), st.peek());
-            if next.is_none() || pk.is_none() {
-                break;
-            }
-            if next != pk {
-                break;
+        for want in self.0.chars() {
+            let ok = match (st.peek(), mode) {
+                (None, _) => false,
+                (Some(c), CaseMode::Exact) => c == want,
+                (Some(c), CaseMode::Ascii) => c.eq_ignore_ascii_case(&want),
+                (Some(c), CaseMode::Unicode) => fold_case(c) == fold_case(want),
+            };
+            if !ok {
+                let ix = st.index();
+                st.reset(hold);
+                return Err(ParseError::Fail("string not matched", ix));
             }
             let c = st.next().unwrap();
-            have += c.len_utf8();
+            if mode != CaseMode::Exact {
+                matched.push(c);
+            }
         }
-        if expect == have {
-            st.release(hold);
-            return Ok(self.0.clone());
+        st.release(hold);
+        if mode == CaseMode::Exact {
+            Ok(self.0.clone())
+        } else {
+            Ok(matched)
+        }
+    }
+}
+
+/// A node of the trie used by `Literals`.
+#[derive(Clone, Debug, Default)]
+struct TrieNode {
+    /// Sorted by char, pointing to nodes.
+    children: Vec<(char, usize)>,
+    /// Index of the value of the literal ending here.
+    value: Option<usize>,
+}
+
+/// Literals matches the longest of a set of literal strings, and returns the value associated
+/// with it. The literals are compiled into a trie, so that matching takes a single pass over the
+/// input, independent of the number of literals. This is much faster than an `Alternative` of
+/// `StringParser`s for keywords, operators, entity names etc.
+///
+/// ```
+/// use rcombinators::primitives::Literals;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ops = Literals::new(vec![("<", 1), ("<=", 2), ("<<=", 3), ("=", 4)]);
+/// let mut ps = ParseState::new("<<=<<");
+/// assert_eq!(Ok(3), ops.parse(&mut ps));
+/// assert_eq!(Ok(1), ops.parse(&mut ps));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Literals<V: Clone> {
+    nodes: Vec<TrieNode>,
+    values: Vec<V>,
+    case_insensitive: bool,
+}
+
+impl<V: Clone> Literals<V> {
+    /// Create a Literals parser for the given pairs of literal and value.
+    pub fn new<S: AsRef<str>, I: IntoIterator<Item = (S, V)>>(literals: I) -> Literals<V> {
+        Literals::build(literals, false)
+    }
+    /// Create a Literals parser ignoring case, like `StringParser::new_case_insensitive()`.
+    pub fn new_case_insensitive<S: AsRef<str>, I: IntoIterator<Item = (S, V)>>(
+        literals: I,
+    ) -> Literals<V> {
+        Literals::build(literals, true)
+    }
+
+    fn build<S: AsRef<str>, I: IntoIterator<Item = (S, V)>>(
+        literals: I,
+        case_insensitive: bool,
+    ) -> Literals<V> {
+        let mut l = Literals {
+            nodes: vec![TrieNode::default()],
+            values: vec![],
+            case_insensitive,
+        };
+        for (lit, v) in literals {
+            l.insert(lit, v);
+        }
+        l
+    }
+
+    /// Add a literal. If it is already present, its value is replaced.
+    pub fn insert<S: AsRef<str>>(&mut self, literal: S, value: V) {
+        let mut node = 0;
+        for c in literal.as_ref().chars() {
+            let c = self.fold(c);
+            node = match self.child(node, c) {
+                Ok(next) => next,
+                Err(pos) => {
+                    self.nodes.push(TrieNode::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(pos, (c, next));
+                    next
+                }
+            };
+        }
+        match self.nodes[node].value {
+            Some(ix) => self.values[ix] = value,
+            None => {
+                self.values.push(value);
+                self.nodes[node].value = Some(self.values.len() - 1);
+            }
+        }
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.case_insensitive {
+            fold_case(c)
+        } else {
+            c
+        }
+    }
+
+    /// Find the child of `node` for `c`, or the position where it would have to be inserted.
+    fn child(&self, node: usize, c: char) -> Result<usize, usize> {
+        let children = &self.nodes[node].children;
+        children
+            .binary_search_by_key(&c, |&(cc, _)| cc)
+            .map(|i| children[i].1)
+    }
+}
+
+impl<V: Clone> Parser for Literals<V> {
+    type Result = V;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let hold = st.hold();
+        let mut node = 0;
+        // Value and end of the longest literal found so far.
+        let mut best = self.nodes[0].value.map(|v| (v, start));
+        while let Some(c) = st.peek() {
+            match self.child(node, self.fold(c)) {
+                Ok(next) => node = next,
+                Err(_) => break,
+            }
+            st.next();
+            if let Some(v) = self.nodes[node].value {
+                best = Some((v, st.index()));
+            }
         }
-        let ix = st.index();
         st.reset(hold);
-        return Err(ParseError::Fail("string not matched", ix));
+        match best {
+            Some((v, end)) => {
+                st.skip_to(end);
+                Ok(self.values[v].clone())
+            }
+            None => Err(ParseError::Fail("no literal matched", start)),
+        }
+    }
+}
+
+/// Integer is implemented by the primitive integer types, and used by the `Int` parser.
+pub trait Integer: Sized {
+    /// Whether the type can represent negative numbers.
+    const SIGNED: bool;
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, std::num::ParseIntError>;
+}
+
+/// Macro for implementing Integer. Not for public use.
+macro_rules! integer_impl {
+    ( $($t:ty : $signed:expr),* ) => {
+        $(
+            impl Integer for $t {
+                const SIGNED: bool = $signed;
+                fn from_str_radix(s: &str, radix: u32) -> Result<Self, std::num::ParseIntError> {
+                    <$t>::from_str_radix(s, radix)
+                }
+            }
+        )*
     }
 }
 
+integer_impl!(i8: true, i16: true, i32: true, i64: true, i128: true, isize: true);
+integer_impl!(u8: false, u16: false, u32: false, u64: false, u128: false, usize: false);
+
 /// Int parses an integer resulting in `IType`. It is recommended to use the specializations such
 /// as `Int64`, `Uint32`, etc.
 ///
+/// By default, decimal integers with an optional `-` sign (for signed types) are parsed. Other
+/// radixes, radix prefixes (`0x`, `0o`, `0b`), a `+` sign, and digit group separators can be
+/// enabled using the builder methods:
+///
+/// ```
+/// use rcombinators::primitives::Int64;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("+0xff_ff");
+/// let mut p = Int64::new().prefixed().allow_plus().separator('_');
+/// assert_eq!(Ok(0xffff), p.parse(&mut ps));
+/// ```
+///
+/// If the number doesn't fit into `IType`, parsing fails with `Fail("integer too large", ix)` or
+/// `Fail("integer too small", ix)`, `ix` being the position of the number's first character.
+///
 /// This is an optimized parser, not using combinators.
-pub struct Int<IType: Default + str::FromStr>(IType);
+pub struct Int<IType: Integer> {
+    radix: u32,
+    prefixed: bool,
+    plus: bool,
+    separator: Option<char>,
+    _t: PhantomData<IType>,
+}
 
 /// Parse a 128 bit signed integer.
 pub type Int128 = Int<i128>;
@@ -75,235 +292,1531 @@ pub type Uint16 = Int<u16>;
 /// Parse a 8 bit unsigned integer.
 pub type Uint8 = Int<u8>;
 
-impl<IType: Default + str::FromStr> Int<IType> {
-    pub fn new() -> Int<IType> {
-        Int(IType::default())
+impl<IType: Integer> Int<IType> {
+    pub fn new() -> Int<IType> {
+        Int {
+            radix: 10,
+            prefixed: false,
+            plus: false,
+            separator: None,
+            _t: PhantomData,
+        }
+    }
+    /// Parse integers in base `radix`, which must be between 2 and 36.
+    pub fn radix(mut self, radix: u32) -> Int<IType> {
+        assert!((2..=36).contains(&radix), "radix must be between 2 and 36");
+        self.radix = radix;
+        self
+    }
+    /// Detect the prefixes `0x`, `0o` and `0b` (also in upper case), switching to radix 16, 8 and
+    /// 2, respectively. Numbers without prefix are parsed in the configured radix.
+    pub fn prefixed(mut self) -> Int<IType> {
+        self.prefixed = true;
+        self
+    }
+    /// Accept an explicit `+` sign.
+    pub fn allow_plus(mut self) -> Int<IType> {
+        self.plus = true;
+        self
+    }
+    /// Accept `sep` between digits, e.g. `_` in `1_000_000`. It is ignored for the value.
+    pub fn separator(mut self, sep: char) -> Int<IType> {
+        self.separator = Some(sep);
+        self
+    }
+
+    /// Consume a radix prefix followed by a digit in that radix, returning the radix.
+    fn parse_prefix(&self, st: &mut ParseState<impl Iterator<Item = char>>) -> Option<u32> {
+        if !self.prefixed || st.peek() != Some('0') {
+            return None;
+        }
+        let hold = st.hold();
+        st.next();
+        let radix = match st.next() {
+            Some('x') | Some('X') => 16,
+            Some('o') | Some('O') => 8,
+            Some('b') | Some('B') => 2,
+            _ => 0,
+        };
+        match st.peek() {
+            Some(c) if radix > 0 && c.is_digit(radix) => {
+                st.release(hold);
+                Some(radix)
+            }
+            _ => {
+                st.reset(hold);
+                None
+            }
+        }
+    }
+
+    fn parse_digits(&self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<IType> {
+        let start = st.index();
+        // Optimization for most ints: digits are ASCII, so they are collected into a fixed byte
+        // buffer, spilling into a vector only for very long inputs.
+        const BUFSIZE: usize = 32;
+        let mut buf = [0u8; BUFSIZE];
+        let mut widebuf: Option<Vec<u8>> = None;
+        let mut i = 0;
+        match st.peek() {
+            None => return Err(ParseError::EOF),
+            Some('-') if IType::SIGNED => {
+                buf[i] = b'-';
+                i += 1;
+                st.next();
+            }
+            Some('+') if self.plus => {
+                st.next();
+            }
+            Some(c) if c.is_digit(self.radix) => {}
+            Some(_) => return Err(ParseError::Fail("not start of integer", start)),
+        }
+        let radix = self.parse_prefix(st).unwrap_or(self.radix);
+
+        let sign_len = i;
+        loop {
+            match st.peek() {
+                Some(c) if c.is_digit(radix) => {
+                    match widebuf {
+                        None if i < BUFSIZE => buf[i] = c as u8,
+                        None => {
+                            let mut v = buf.to_vec();
+                            v.push(c as u8);
+                            widebuf = Some(v);
+                        }
+                        Some(ref mut v) => v.push(c as u8),
+                    }
+                    i += 1;
+                    st.next();
+                }
+                Some(c) if Some(c) == self.separator && i > sign_len => {
+                    // Only accept separators followed by another digit.
+                    st.next();
+                    match st.peek() {
+                        Some(d) if d.is_digit(radix) => {}
+                        _ => {
+                            st.undo_next();
+                            break;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+        if i == sign_len {
+            return Err(ParseError::Fail("no appropriate integer found", st.index()));
+        }
+        let digits = match widebuf {
+            None => &buf[..i],
+            Some(ref v) => &v[..],
+        };
+        // Only ASCII characters have been stored.
+        let digits =
+            std::str::from_utf8(digits).map_err(|_| ParseError::Fail("not a number", start))?;
+        IType::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => ParseError::Fail("integer too large", start),
+            IntErrorKind::NegOverflow => ParseError::Fail("integer too small", start),
+            _ => ParseError::Fail("not a number", start),
+        })
+    }
+}
+
+impl<IType: Integer> Default for Int<IType> {
+    fn default() -> Int<IType> {
+        Int::new()
+    }
+}
+
+impl<IType: Integer> Parser for Int<IType> {
+    type Result = IType;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let hold = st.hold();
+        match self.parse_digits(st) {
+            Ok(i) => {
+                st.release(hold);
+                Ok(i)
+            }
+            Err(e) => {
+                st.reset(hold);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn assemble_float(
+    s: Option<()>,
+    big: String,
+    dot: Option<String>,
+    mut little: Option<String>,
+    exp: Option<((), i32)>,
+) -> ParseResult<f64> {
+    if dot.is_some() && little.is_none() {
+        little = Some("0".to_string());
+    }
+    assert!((dot.is_some() && little.is_some()) || (dot.is_none() && little.is_none()));
+    let bigf = match f64::from_str(&big) {
+        Ok(f) => f,
+        Err(e) => return Err(execerr(e.to_string())),
+    };
+    let mut littlef = 0.;
+    if let Some(mut d) = dot {
+        d.push_str(little.as_ref().unwrap());
+        littlef = match f64::from_str(&d) {
+            Ok(f) => f,
+            Err(e) => return Err(execerr(e.to_string())),
+        }
+    }
+    let mut multiplier: f64 = if s.is_some() { -1. } else { 1. };
+    if let Some((_, e)) = exp {
+        multiplier *= 10_f64.powi(e);
+    }
+    Ok(multiplier * (bigf + littlef))
+}
+
+/// float parses floats in the format of `[-]dd[.[dd]][e[-]ddd]`.
+///
+/// This parser is built from combinators and mostly serves as an example. `Float` is much faster,
+/// accepts more notations, and rounds correctly.
+pub fn float() -> impl Parser<Result = f64> {
+    let digits_set = "0123456789";
+    let minus = Maybe::new(Ignore::new(StringParser::new("-")));
+    let digits = string_of(digits_set, RepeatSpec::Min(1));
+    let point = Maybe::new(StringParser::new("."));
+    let smalldigits = Maybe::new(string_of(digits_set, RepeatSpec::Min(1)));
+    let exp = Maybe::new(Sequence::new((
+        Ignore::new(StringParser::new("e")),
+        Int32::new(),
+    )));
+    Sequence::new((minus, digits, point, smalldigits, exp))
+        .apply(|(m, d, p, sd, exp)| assemble_float(m, d, p, sd, exp))
+}
+
+/// Floating is implemented by `f32` and `f64`, and used by the `Float` parser.
+pub trait Floating: FromStr {}
+
+impl Floating for f32 {}
+impl Floating for f64 {}
+
+/// FloatSyntax describes which notations of floating point numbers a `Float` parser accepts. A
+/// leading `-` sign, digits before the decimal point, and a fractional part (`.` followed by
+/// digits) are always accepted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatSyntax {
+    /// Accept a leading `+` sign.
+    pub plus: bool,
+    /// Accept numbers without digits before the decimal point, like `.5`.
+    pub leading_dot: bool,
+    /// Accept numbers without digits after the decimal point, like `5.`.
+    pub trailing_dot: bool,
+    /// Accept leading zeros, like `05`.
+    pub leading_zeros: bool,
+    /// Accept an exponent like `e5`, `E-5` or `e+5`.
+    pub exponent: bool,
+    /// Accept `inf`, `infinity` and `nan` (in any case).
+    pub special: bool,
+    /// Accept the given separator between digits, like `_` in `1_000.0`.
+    pub separator: Option<char>,
+}
+
+impl FloatSyntax {
+    /// Numbers as specified by JSON: `-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+    pub fn json() -> FloatSyntax {
+        FloatSyntax {
+            plus: false,
+            leading_dot: false,
+            trailing_dot: false,
+            leading_zeros: false,
+            exponent: true,
+            special: false,
+            separator: None,
+        }
+    }
+    /// Numbers as written in Rust source code, e.g. `1_000.5e3` or `5.`, with optional `-` sign.
+    pub fn rust() -> FloatSyntax {
+        FloatSyntax {
+            plus: false,
+            leading_dot: false,
+            trailing_dot: true,
+            leading_zeros: true,
+            exponent: true,
+            special: false,
+            separator: Some('_'),
+        }
+    }
+    /// Numbers as accepted by C's `strtod()` (except for hexadecimal floats), e.g. `+.5E3`,
+    /// `5.` or `INF`. This is also what `f64::from_str()` accepts.
+    pub fn c() -> FloatSyntax {
+        FloatSyntax {
+            plus: true,
+            leading_dot: true,
+            trailing_dot: true,
+            leading_zeros: true,
+            exponent: true,
+            special: true,
+            separator: None,
+        }
+    }
+}
+
+impl FloatSyntax {
+    /// Consume a special value (`inf`, `infinity`, `nan`) and append it to `s`.
+    fn parse_special(st: &mut ParseState<impl Iterator<Item = char>>, s: &mut String) -> bool {
+        for word in ["infinity", "inf", "nan"].iter() {
+            let hold = st.hold();
+            if word.chars().all(|w| match st.peek() {
+                Some(c) if c.eq_ignore_ascii_case(&w) => {
+                    st.next();
+                    true
+                }
+                _ => false,
+            }) {
+                st.release(hold);
+                s.push_str(word);
+                return true;
+            }
+            st.reset(hold);
+        }
+        false
+    }
+
+    /// Consume digits and separators between them, appending the digits to `s`. Returns the
+    /// number of digits. Leading zeros are only checked for the integer part (`int`).
+    fn parse_digits(
+        &self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+        s: &mut String,
+        int: bool,
+    ) -> usize {
+        let mut n = 0;
+        loop {
+            match st.peek() {
+                Some(c) if c.is_ascii_digit() => {
+                    if int && n == 1 && !self.leading_zeros && s.ends_with('0') {
+                        break;
+                    }
+                    s.push(c);
+                    st.next();
+                    n += 1;
+                }
+                Some(c) if n > 0 && Some(c) == self.separator => {
+                    st.next();
+                    match st.peek() {
+                        Some(d) if d.is_ascii_digit() => {}
+                        _ => {
+                            st.undo_next();
+                            break;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+        n
+    }
+
+    /// Consume a number in this syntax, and return it in a notation understood by `f64::from_str`.
+    fn parse_text(&self, st: &mut ParseState<impl Iterator<Item = char>>) -> ParseResult<String> {
+        let start = st.index();
+        let mut s = String::with_capacity(16);
+        match st.peek() {
+            None => return Err(ParseError::EOF),
+            Some('-') => {
+                s.push('-');
+                st.next();
+            }
+            Some('+') if self.plus => {
+                st.next();
+            }
+            _ => {}
+        }
+        if self.special && FloatSyntax::parse_special(st, &mut s) {
+            return Ok(s);
+        }
+
+        let int_digits = self.parse_digits(st, &mut s, true);
+        let mut frac_digits = 0;
+        if st.peek() == Some('.') && (int_digits > 0 || self.leading_dot) {
+            let hold = st.hold();
+            st.next();
+            let mut frac = String::from(".");
+            frac_digits = self.parse_digits(st, &mut frac, false);
+            if frac_digits > 0 || (int_digits > 0 && self.trailing_dot) {
+                st.release(hold);
+                s.push_str(&frac);
+            } else {
+                st.reset(hold);
+            }
+        }
+        if int_digits == 0 && frac_digits == 0 {
+            return Err(ParseError::Fail("not start of float", start));
+        }
+
+        if self.exponent {
+            if let Some(e) = st.peek().filter(|&c| c == 'e' || c == 'E') {
+                let hold = st.hold();
+                st.next();
+                let mut exp = e.to_string();
+                if let Some(sign) = st.peek().filter(|&c| c == '-' || c == '+') {
+                    exp.push(sign);
+                    st.next();
+                }
+                if self.parse_digits(st, &mut exp, false) > 0 {
+                    st.release(hold);
+                    s.push_str(&exp);
+                } else {
+                    st.reset(hold);
+                }
+            }
+        }
+        Ok(s)
+    }
+}
+
+impl Default for FloatSyntax {
+    fn default() -> FloatSyntax {
+        FloatSyntax::c()
+    }
+}
+
+/// Float parses a floating point number resulting in `F`, which is either `f32` or `f64`. The
+/// accepted notation is given by a `FloatSyntax`; the value is the correctly rounded one, as
+/// returned by `F::from_str()`.
+///
+/// This is an optimized parser, not using combinators.
+///
+/// ```
+/// use rcombinators::primitives::{Float32, Float64};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("+.5e1 0.1");
+/// assert_eq!(Ok(5.), Float64::new().parse(&mut ps));
+/// assert!(Float64::json().parse(&mut ps).is_err());
+/// ps.next();
+/// assert_eq!(Ok(0.1_f32), Float32::json().parse(&mut ps));
+/// ```
+pub struct Float<F: Floating> {
+    syntax: FloatSyntax,
+    _f: PhantomData<F>,
+}
+
+/// Parse a 64 bit floating point number.
+pub type Float64 = Float<f64>;
+/// Parse a 32 bit floating point number.
+pub type Float32 = Float<f32>;
+
+impl<F: Floating> Float<F> {
+    /// Create a parser for the default syntax, `FloatSyntax::c()`.
+    pub fn new() -> Float<F> {
+        Float::with_syntax(FloatSyntax::default())
+    }
+    /// Create a parser for JSON numbers.
+    pub fn json() -> Float<F> {
+        Float::with_syntax(FloatSyntax::json())
+    }
+    /// Create a parser for numbers as written in Rust.
+    pub fn rust() -> Float<F> {
+        Float::with_syntax(FloatSyntax::rust())
+    }
+    /// Create a parser for numbers as accepted by C.
+    pub fn c() -> Float<F> {
+        Float::with_syntax(FloatSyntax::c())
+    }
+    pub fn with_syntax(syntax: FloatSyntax) -> Float<F> {
+        Float {
+            syntax,
+            _f: PhantomData,
+        }
+    }
+}
+
+impl<F: Floating> Default for Float<F> {
+    fn default() -> Float<F> {
+        Float::new()
+    }
+}
+
+impl<F: Floating> Parser for Float<F> {
+    type Result = F;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let hold = st.hold();
+        match self.syntax.parse_text(st) {
+            Ok(s) => match F::from_str(&s) {
+                Ok(f) => {
+                    st.release(hold);
+                    Ok(f)
+                }
+                Err(_) => {
+                    st.reset(hold);
+                    Err(ParseError::Fail("not a number", start))
+                }
+            },
+            Err(e) => {
+                st.reset(hold);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Decimal is an exact representation of a decimal number: its value is `digits * 10^exponent`,
+/// negated if `is_negative()`. Leading zeros are removed from the digits, but trailing zeros are
+/// kept, as they usually convey precision: `1.50` has the digits `150` and exponent `-2`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    negative: bool,
+    digits: String,
+    exponent: i64,
+}
+
+impl Decimal {
+    /// Create a Decimal from its parts. `digits` must only consist of ASCII digits.
+    pub fn new<S: AsRef<str>>(negative: bool, digits: S, exponent: i64) -> Decimal {
+        let digits = digits.as_ref().trim_start_matches('0');
+        assert!(
+            digits.chars().all(|c| c.is_ascii_digit()),
+            "decimal digits must be ASCII digits"
+        );
+        Decimal {
+            negative,
+            digits: if digits.is_empty() { "0" } else { digits }.to_string(),
+            exponent,
+        }
+    }
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+    pub fn exponent(&self) -> i64 {
+        self.exponent
+    }
+
+    /// Parse text as returned by `FloatSyntax::parse_text()` (without special values).
+    fn from_text(s: &str) -> Option<Decimal> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(ix) => (&s[..ix], s[ix + 1..].parse::<i64>().ok()),
+            None => (s, Some(0)),
+        };
+        let (int, frac) = match mantissa.find('.') {
+            Some(ix) => (&mantissa[..ix], &mantissa[ix + 1..]),
+            None => (mantissa, ""),
+        };
+        let digits = format!("{}{}", int, frac);
+        let exponent = match exponent.and_then(|e| e.checked_sub(i64::try_from(frac.len()).ok()?)) {
+            Some(e) => e,
+            // Zero is exact, whatever the exponent.
+            None if digits.bytes().all(|b| b == b'0') => 0,
+            None => return None,
+        };
+        Some(Decimal::new(negative, digits, exponent))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let len = self.digits.len() as i64;
+        if self.exponent == 0 {
+            f.write_str(&self.digits)
+        } else if self.exponent < 0 && self.exponent >= -len - 6 {
+            // Plain notation, unless that would require too many zeros after the point.
+            let point = len + self.exponent;
+            if point > 0 {
+                let point = point as usize;
+                write!(f, "{}.{}", &self.digits[..point], &self.digits[point..])
+            } else {
+                let zeros = "0".repeat(-point as usize);
+                write!(f, "0.{}{}", zeros, self.digits)
+            }
+        } else {
+            write!(f, "{}e{}", self.digits, self.exponent)
+        }
+    }
+}
+
+/// FromDecimal is implemented by types that a `Decimal` can be converted into without going
+/// through a binary floating point number. Implement it for your own types (e.g. fixed-point
+/// money amounts) to use them with `DecimalParser`.
+pub trait FromDecimal: Sized {
+    /// Convert `d`, or return a message explaining why this isn't possible.
+    fn from_decimal(d: Decimal) -> Result<Self, &'static str>;
+}
+
+impl FromDecimal for Decimal {
+    fn from_decimal(d: Decimal) -> Result<Self, &'static str> {
+        Ok(d)
+    }
+}
+
+impl FromDecimal for f64 {
+    fn from_decimal(d: Decimal) -> Result<Self, &'static str> {
+        f64::from_str(&d.to_string()).map_err(|_| "not a number")
+    }
+}
+
+impl FromDecimal for f32 {
+    fn from_decimal(d: Decimal) -> Result<Self, &'static str> {
+        f32::from_str(&d.to_string()).map_err(|_| "not a number")
+    }
+}
+
+/// Integers are only converted from decimals without fractional part.
+impl<I: Integer> FromDecimal for I {
+    fn from_decimal(d: Decimal) -> Result<Self, &'static str> {
+        let too_large = if d.negative {
+            "integer too small"
+        } else {
+            "integer too large"
+        };
+        let mut digits = d.digits;
+        if digits == "0" {
+            // Zero, whatever the exponent.
+        } else if d.exponent >= 0 {
+            // No integer type has more than 40 digits.
+            if d.exponent > 40 - digits.len() as i64 {
+                return Err(too_large);
+            }
+            digits.push_str(&"0".repeat(d.exponent as usize));
+        } else {
+            let frac = usize::try_from(d.exponent.unsigned_abs()).unwrap_or(usize::MAX);
+            if frac > digits.len() || !digits[digits.len() - frac..].bytes().all(|b| b == b'0') {
+                return Err("not an integer");
+            }
+            digits.truncate(digits.len() - frac);
+        }
+        if d.negative && digits != "0" {
+            if !I::SIGNED {
+                return Err(too_large);
+            }
+            digits.insert(0, '-');
+        }
+        I::from_str_radix(&digits, 10).map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => too_large,
+            _ => "not a number",
+        })
+    }
+}
+
+/// DecimalParser parses a decimal number exactly, without rounding, and converts it into `T`
+/// (by default `Decimal`). It accepts the same notations as `Float` with the same `FloatSyntax`,
+/// except for the special values `inf` and `nan`.
+///
+/// If the conversion fails, `Fail(msg, ix)` is returned with `FromDecimal`'s message and the
+/// position of the number.
+///
+/// ```
+/// use rcombinators::primitives::{Decimal, DecimalParser};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("-12.50 1e3");
+/// assert_eq!(
+///     Ok(Decimal::new(true, "1250", -2)),
+///     DecimalParser::new().parse(&mut ps)
+/// );
+/// ps.next();
+/// assert_eq!(Ok(1000_u32), DecimalParser::new().parse(&mut ps));
+/// ```
+pub struct DecimalParser<T: FromDecimal = Decimal> {
+    syntax: FloatSyntax,
+    _t: PhantomData<T>,
+}
+
+impl<T: FromDecimal> DecimalParser<T> {
+    /// Create a parser for the syntax `FloatSyntax::c()` (without special values).
+    pub fn new() -> DecimalParser<T> {
+        DecimalParser::with_syntax(FloatSyntax::c())
+    }
+    pub fn with_syntax(syntax: FloatSyntax) -> DecimalParser<T> {
+        DecimalParser {
+            syntax: FloatSyntax {
+                special: false,
+                ..syntax
+            },
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T: FromDecimal> Default for DecimalParser<T> {
+    fn default() -> DecimalParser<T> {
+        DecimalParser::new()
+    }
+}
+
+impl<T: FromDecimal> Parser for DecimalParser<T> {
+    type Result = T;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let hold = st.hold();
+        let r = self.syntax.parse_text(st).and_then(|s| {
+            let d = Decimal::from_text(&s).ok_or(ParseError::Fail("exponent too large", start))?;
+            T::from_decimal(d).map_err(|msg| ParseError::Fail(msg, start))
+        });
+        match r {
+            Ok(r) => {
+                st.release(hold);
+                Ok(r)
+            }
+            Err(e) => {
+                st.reset(hold);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// TakeUntil consumes all input up to a terminating string, and returns it. The terminator itself
+/// is not consumed. It fails without consuming anything if the terminator is not found.
+///
+/// This is much faster than repeatedly applying a parser until the terminator is found, and
+/// useful for block comments, here documents and the like.
+pub struct TakeUntil(Vec<char>);
+
+impl TakeUntil {
+    pub fn new<S: AsRef<str>>(terminator: S) -> TakeUntil {
+        TakeUntil(terminator.as_ref().chars().collect())
+    }
+}
+
+impl Parser for TakeUntil {
+    type Result = String;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        match st.take_until_literal(&self.0) {
+            Some(s) => Ok(s),
+            None => Err(ParseError::Fail("terminator not found", st.index())),
+        }
+    }
+}
+
+/// Consume all input up to `terminator`. See `TakeUntil`.
+pub fn take_until<S: AsRef<str>>(terminator: S) -> TakeUntil {
+    TakeUntil::new(terminator)
+}
+
+/// Nothing is a parser that always succeeds.
+pub struct Nothing;
+
+impl Parser for Nothing {
+    type Result = ();
+    fn parse(
+        &mut self,
+        _: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        Ok(())
+    }
+}
+
+/// A set of characters. Efficient matching depending on number of characters (a HashSet is much
+/// slower than a Vec for less than ~20 chars).
+enum CharSet {
+    One(char),
+    Some(Vec<char>),
+    Many(HashSet<char>),
+    Range(RangeInclusive<char>),
+    Class(CharClass),
+    Predicate(Box<dyn Fn(char) -> bool>),
+}
+
+/// Threshold above which a HashSet is used instead of a Vec. Empirically determined.
+const MATCHSPEC_MANY_THRESHOLD: usize = 20;
+
+impl CharSet {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharSet::One(cc) => c == *cc,
+            CharSet::Some(cs) => cs.contains(&c),
+            CharSet::Many(cs) => cs.contains(&c),
+            CharSet::Range(r) => r.contains(&c),
+            CharSet::Class(cl) => cl.matches(c),
+            CharSet::Predicate(f) => f(c),
+        }
+    }
+}
+
+/// CharClass is a predefined class of characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    /// Unicode alphabetic characters (`char::is_alphabetic`).
+    Alphabetic,
+    /// Unicode alphanumeric characters (`char::is_alphanumeric`).
+    Alphanumeric,
+    /// Unicode numeric characters (`char::is_numeric`).
+    Numeric,
+    /// Unicode lowercase characters (`char::is_lowercase`).
+    Lowercase,
+    /// Unicode uppercase characters (`char::is_uppercase`).
+    Uppercase,
+    /// Unicode whitespace (`char::is_whitespace`).
+    Whitespace,
+    /// `0` to `9`.
+    AsciiDigit,
+    /// `0` to `9`, `a` to `f` and `A` to `F`.
+    AsciiHexDigit,
+    /// Characters that may start an identifier according to Unicode (UAX #31).
+    XidStart,
+    /// Characters that may continue an identifier according to Unicode (UAX #31).
+    XidContinue,
+}
+
+impl CharClass {
+    pub fn matches(self, c: char) -> bool {
+        match self {
+            CharClass::Alphabetic => c.is_alphabetic(),
+            CharClass::Alphanumeric => c.is_alphanumeric(),
+            CharClass::Numeric => c.is_numeric(),
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::AsciiDigit => c.is_ascii_digit(),
+            CharClass::AsciiHexDigit => c.is_ascii_hexdigit(),
+            CharClass::XidStart => UnicodeXID::is_xid_start(c),
+            CharClass::XidContinue => UnicodeXID::is_xid_continue(c),
+        }
+    }
+}
+
+/// MatchSpec describes a set of characters, as used by `OneOf` and `string_of()`. It is the union
+/// of any number of explicitly listed characters, character ranges, `CharClass`es and predicates.
+///
+/// All of these can be converted into a MatchSpec using `into()`, so that parsers taking a
+/// MatchSpec also accept strings of characters, ranges like `'a'..='z'`, and classes:
+///
+/// ```
+/// use rcombinators::primitives::{string_of, CharClass, MatchSpec};
+/// use rcombinators::combinators::RepeatSpec;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new("a_Zß9-");
+/// let word = MatchSpec::from(CharClass::Alphabetic).or('0'..='9').or("_");
+/// assert_eq!(Ok("a_Zß9".to_string()), string_of(word, RepeatSpec::Min(1)).parse(&mut ps));
+/// ```
+pub struct MatchSpec(Vec<CharSet>);
+
+impl MatchSpec {
+    /// A set consisting of the characters in `chars`.
+    pub fn new<S: AsRef<str>>(chars: S) -> MatchSpec {
+        let chars = chars.as_ref();
+        let set = if chars.chars().count() == 1 {
+            CharSet::One(chars.chars().next().unwrap())
+        } else if chars.len() <= MATCHSPEC_MANY_THRESHOLD {
+            CharSet::Some(chars.chars().collect())
+        } else {
+            CharSet::Many(chars.chars().collect())
+        };
+        MatchSpec(vec![set])
+    }
+    /// A set consisting of all characters for which `f` returns true.
+    pub fn predicate<F: Fn(char) -> bool + 'static>(f: F) -> MatchSpec {
+        MatchSpec(vec![CharSet::Predicate(Box::new(f))])
+    }
+    /// The union of this set and `other`.
+    pub fn or<M: Into<MatchSpec>>(mut self, other: M) -> MatchSpec {
+        self.0.extend(other.into().0);
+        self
+    }
+    pub fn matches(&self, c: char) -> bool {
+        self.0.iter().any(|s| s.matches(c))
+    }
+}
+
+impl From<&str> for MatchSpec {
+    fn from(chars: &str) -> MatchSpec {
+        MatchSpec::new(chars)
+    }
+}
+
+impl From<&String> for MatchSpec {
+    fn from(chars: &String) -> MatchSpec {
+        MatchSpec::new(chars)
+    }
+}
+
+impl From<String> for MatchSpec {
+    fn from(chars: String) -> MatchSpec {
+        MatchSpec::new(chars)
+    }
+}
+
+impl From<char> for MatchSpec {
+    fn from(c: char) -> MatchSpec {
+        MatchSpec(vec![CharSet::One(c)])
+    }
+}
+
+impl From<RangeInclusive<char>> for MatchSpec {
+    fn from(r: RangeInclusive<char>) -> MatchSpec {
+        MatchSpec(vec![CharSet::Range(r)])
+    }
+}
+
+impl From<CharClass> for MatchSpec {
+    fn from(cl: CharClass) -> MatchSpec {
+        MatchSpec(vec![CharSet::Class(cl)])
+    }
+}
+
+/// OneOf matches any character that is (or is not) in its set.
+pub struct OneOf(MatchSpec, bool);
+
+impl OneOf {
+    pub fn new<M: Into<MatchSpec>>(chars: M) -> OneOf {
+        OneOf(chars.into(), false)
+    }
+    /// Create a OneOf parser that parses all characters *not* in the given set.
+    pub fn new_none_of<M: Into<MatchSpec>>(chars: M) -> OneOf {
+        OneOf(chars.into(), true)
+    }
+}
+
+impl Parser for OneOf {
+    type Result = char;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        match st.peek() {
+            Some(c) => {
+                if self.0.matches(c) ^ self.1 {
+                    st.next();
+                    Ok(c)
+                } else {
+                    Err(ParseError::Fail("char not matched", st.index()))
+                }
+            }
+            _ => Err(ParseError::EOF),
+        }
+    }
+}
+
+/// A parser that parses a string consisting of characters `chars`, which can be a string of
+/// characters or anything else convertible into a `MatchSpec`.
+pub fn string_of<M: Into<MatchSpec>>(chars: M, rp: RepeatSpec) -> impl Parser<Result = String> {
+    let oo = OneOf::new(chars);
+    Repeat::new(oo, rp).collect()
+}
+
+/// A parser that parses a string consisting of any characters not in the set.
+pub fn string_none_of<M: Into<MatchSpec>>(
+    chars: M,
+    rp: RepeatSpec,
+) -> impl Parser<Result = String> {
+    let oo = OneOf::new_none_of(chars);
+    Repeat::new(oo, rp).collect()
+}
+
+/// A parser that parses one character for which `f` returns true.
+pub fn satisfy<F: Fn(char) -> bool + 'static>(f: F) -> OneOf {
+    OneOf::new(MatchSpec::predicate(f))
+}
+
+/// Identifier parses an identifier: a character from a set of allowed first characters, followed
+/// by any number of characters from a set of allowed subsequent characters. Reserved words can be
+/// excluded; if one of them is found, parsing fails with `Fail("keyword used as identifier", ix)`
+/// at its position.
+///
+/// ```
+/// use rcombinators::primitives::identifier;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut p = identifier().reserved(&["if", "else"]);
+/// assert_eq!(Ok("iffy".to_string()), p.parse(&mut ParseState::new("iffy")));
+/// assert!(p.parse(&mut ParseState::new("if")).is_err());
+/// ```
+pub struct Identifier {
+    first: MatchSpec,
+    rest: MatchSpec,
+    reserved: HashSet<String>,
+}
+
+impl Identifier {
+    /// Create a parser for identifiers starting with a character from `first`, and continuing
+    /// with characters from `rest`.
+    pub fn new<M1: Into<MatchSpec>, M2: Into<MatchSpec>>(first: M1, rest: M2) -> Identifier {
+        Identifier {
+            first: first.into(),
+            rest: rest.into(),
+            reserved: HashSet::new(),
+        }
+    }
+    /// Identifiers consisting of ASCII letters, digits and `_`, not starting with a digit.
+    pub fn ascii() -> Identifier {
+        let letters = MatchSpec::from('a'..='z').or('A'..='Z').or('_');
+        Identifier::new(
+            letters,
+            MatchSpec::from('a'..='z')
+                .or('A'..='Z')
+                .or('_')
+                .or('0'..='9'),
+        )
+    }
+    /// Identifiers as defined by Unicode (UAX #31), also allowing a leading `_`, as in Rust.
+    pub fn unicode() -> Identifier {
+        Identifier::new(
+            MatchSpec::from(CharClass::XidStart).or('_'),
+            CharClass::XidContinue,
+        )
+    }
+    /// Exclude `words` from being parsed as identifiers.
+    pub fn reserved<S: AsRef<str>>(mut self, words: &[S]) -> Identifier {
+        self.reserved
+            .extend(words.iter().map(|w| w.as_ref().to_string()));
+        self
+    }
+}
+
+impl Parser for Identifier {
+    type Result = String;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let mut id = String::new();
+        match st.peek() {
+            None => return Err(ParseError::EOF),
+            Some(c) if self.first.matches(c) => {
+                id.push(c);
+            }
+            Some(_) => return Err(ParseError::Fail("not start of identifier", start)),
+        }
+        let hold = st.hold();
+        st.next();
+        while let Some(c) = st.peek() {
+            if !self.rest.matches(c) {
+                break;
+            }
+            id.push(c);
+            st.next();
+        }
+        if self.reserved.contains(&id) {
+            st.reset(hold);
+            return Err(ParseError::Fail("keyword used as identifier", start));
+        }
+        st.release(hold);
+        Ok(id)
+    }
+}
+
+/// Parse an ASCII identifier. See `Identifier` for other character sets and reserved words.
+pub fn identifier() -> Identifier {
+    Identifier::ascii()
+}
+
+/// whitespace consumes any number of tabs, spaces, newlines.
+pub fn whitespace() -> impl Parser<Result = ()> {
+    Ignore::new(Repeat::new(OneOf::new(" \n\r\t"), RepeatSpec::Any))
+}
+
+/// QuotedString parses a string literal enclosed in quotes and returns its unescaped contents.
+///
+/// By default, strings are enclosed in `"`, and `\` introduces the escape sequences `\n`, `\r`,
+/// `\t`, `\0`, `\b`, `\f`, `\/`, `\\`, `\"` and `\'`, as well as unicode escapes `\uXXXX` (where
+/// UTF-16 surrogate pairs are combined, as in JSON) and `\u{X...}` (as in Rust). All of this can be
+/// changed using the builder methods; `QuotedString::json()` accepts exactly the strings allowed by
+/// JSON.
+///
+/// Errors are reported with the position of the offending escape sequence, or of the opening
+/// quote if the string is not terminated:
+///
+///   * `Fail("invalid escape sequence", ix)`
+///   * `Fail("invalid unicode escape", ix)`
+///   * `Fail("unpaired surrogate in unicode escape", ix)`
+///   * `Fail("unterminated string", ix)`
+///   * `Fail("control character in string", ix)`, if rejected with `control_chars(false)`
+///
+/// ```
+/// use rcombinators::primitives::QuotedString;
+/// use rcombinators::{ParseState, Parser};
+///
+/// let mut ps = ParseState::new(r#""a\"b\nä""#);
+/// assert_eq!(Ok("a\"b\nä".to_string()), QuotedString::new().parse(&mut ps));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuotedString {
+    quotes: Vec<char>,
+    escape: Option<char>,
+    table: Vec<(char, char)>,
+    utf16_escapes: bool,
+    braced_escapes: bool,
+    raw_prefix: Option<char>,
+    control_chars: bool,
+}
+
+impl Default for QuotedString {
+    fn default() -> QuotedString {
+        QuotedString::new()
     }
 }
 
-impl<IType: Default + str::FromStr<Err = std::num::ParseIntError> + std::convert::TryFrom<i8>>
-    Parser for Int<IType>
-{
-    type Result = IType;
-    fn parse(
-        &mut self,
-        st: &mut ParseState<impl Iterator<Item = char>>,
-    ) -> ParseResult<Self::Result> {
-        // Optimization for most ints.
-        const BUFSIZE: usize = 16;
-        let mut buf: [char; BUFSIZE] = [' '; BUFSIZE];
-        let mut widebuf: Option<Vec<char>> = None;
-        let mut i = 0;
+impl QuotedString {
+    pub fn new() -> QuotedString {
+        QuotedString {
+            quotes: vec!['"'],
+            escape: Some('\\'),
+            table: vec![
+                ('n', '\n'),
+                ('r', '\r'),
+                ('t', '\t'),
+                ('0', '\0'),
+                ('b', '\x08'),
+                ('f', '\x0c'),
+                ('/', '/'),
+                ('\\', '\\'),
+                ('"', '"'),
+                ('\'', '\''),
+            ],
+            utf16_escapes: true,
+            braced_escapes: true,
+            raw_prefix: None,
+            control_chars: true,
+        }
+    }
+    /// Parse JSON strings: only `"` quotes, the escapes `\"`, `\\`, `\/`, `\b`, `\f`, `\n`, `\r`,
+    /// `\t` and `\uXXXX`, and no unescaped control characters.
+    pub fn json() -> QuotedString {
+        QuotedString::new()
+            .escape_table(&[
+                ('"', '"'),
+                ('\\', '\\'),
+                ('/', '/'),
+                ('b', '\x08'),
+                ('f', '\x0c'),
+                ('n', '\n'),
+                ('r', '\r'),
+                ('t', '\t'),
+            ])
+            .braced_escapes(false)
+            .control_chars(false)
+    }
+    /// Set the characters that may enclose a string. A string must end with the same quote
+    /// character it started with.
+    pub fn quotes<S: AsRef<str>>(mut self, quotes: S) -> QuotedString {
+        self.quotes = quotes.as_ref().chars().collect();
+        self
+    }
+    /// Set the character introducing escape sequences.
+    pub fn escape_char(mut self, escape: char) -> QuotedString {
+        self.escape = Some(escape);
+        self
+    }
+    /// Disable escape sequences altogether.
+    pub fn without_escapes(mut self) -> QuotedString {
+        self.escape = None;
+        self
+    }
+    /// Replace the table of simple escape sequences: the escape character followed by the first
+    /// char of a pair is replaced by the second one.
+    pub fn escape_table(mut self, table: &[(char, char)]) -> QuotedString {
+        self.table = table.to_vec();
+        self
+    }
+    /// Enable or disable UTF-16 unicode escapes (`\uXXXX`, with surrogate pairs).
+    pub fn utf16_escapes(mut self, enable: bool) -> QuotedString {
+        self.utf16_escapes = enable;
+        self
+    }
+    /// Enable or disable braced unicode escapes (`\u{X...}`).
+    pub fn braced_escapes(mut self, enable: bool) -> QuotedString {
+        self.braced_escapes = enable;
+        self
+    }
+    /// Allow or reject unescaped control characters (U+0000 to U+001F) in strings.
+    pub fn control_chars(mut self, allow: bool) -> QuotedString {
+        self.control_chars = allow;
+        self
+    }
+    /// Also accept raw strings introduced by `prefix`, as in Rust: `r"..."`, `r#"..."#`, etc.
+    /// Escape sequences are not processed in raw strings, and they end at a quote followed by as
+    /// many `#` as followed the prefix.
+    pub fn raw_strings(mut self, prefix: char) -> QuotedString {
+        self.raw_prefix = Some(prefix);
+        self
+    }
 
-        // Only check for negative sign if type is a signed integer.
-        if IType::try_from(-1 as i8).is_ok() {
+    fn parse_hex(
+        st: &mut ParseState<impl Iterator<Item = char>>,
+        max: usize,
+        terminator: Option<char>,
+    ) -> Option<u32> {
+        let mut n = 0;
+        let mut digits = 0;
+        while digits < max {
             match st.peek() {
-                None => return Err(ParseError::EOF),
-                Some('-') => {
-                    buf[i] = '-';
-                    i += 1;
-                }
-                Some(c) if c.is_digit(10) => {
-                    buf[i] = c;
-                    i += 1;
+                Some(c) if Some(c) == terminator => break,
+                Some(c) if c.is_ascii_hexdigit() => {
+                    n = n * 16 + c.to_digit(16).unwrap();
+                    digits += 1;
+                    st.next();
                 }
-                Some(_) => return Err(ParseError::Fail("not start of integer", st.index())),
+                _ => return None,
+            }
+        }
+        if digits == 0 || (terminator.is_none() && digits < max) {
+            return None;
+        }
+        if let Some(t) = terminator {
+            if st.next() != Some(t) {
+                return None;
             }
         }
+        Some(n)
+    }
 
-        let hold = st.hold();
-        if i > 0 {
+    /// Parse a unicode escape sequence after the `u`; `ix` is the position of the escape
+    /// character.
+    fn parse_unicode(
+        &self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+        ix: usize,
+    ) -> ParseResult<char> {
+        let invalid = ParseError::Fail("invalid unicode escape", ix);
+        if st.peek() == Some('{') {
+            if !self.braced_escapes {
+                return Err(invalid);
+            }
+            st.next();
+            return QuotedString::parse_hex(st, 6, Some('}'))
+                .and_then(std::char::from_u32)
+                .ok_or(invalid);
+        }
+        if !self.utf16_escapes {
+            return Err(invalid);
+        }
+        let high = QuotedString::parse_hex(st, 4, None).ok_or(invalid.clone())?;
+        if !(0xd800..0xe000).contains(&high) {
+            return std::char::from_u32(high).ok_or(invalid);
+        }
+        let unpaired = ParseError::Fail("unpaired surrogate in unicode escape", ix);
+        if high >= 0xdc00 || st.next() != self.escape || st.next() != Some('u') {
+            return Err(unpaired);
+        }
+        match QuotedString::parse_hex(st, 4, None) {
+            Some(low) if (0xdc00..0xe000).contains(&low) => {
+                std::char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+                    .ok_or(invalid)
+            }
+            _ => Err(unpaired),
+        }
+    }
+
+    fn parse_contents(
+        &self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+        start: usize,
+    ) -> ParseResult<String> {
+        let mut hashes = None;
+        if self.raw_prefix.is_some() && st.peek() == self.raw_prefix {
             st.next();
+            let mut n = 0;
+            while st.peek() == Some('#') {
+                st.next();
+                n += 1;
+            }
+            hashes = Some(n);
         }
+        let quote = match st.next() {
+            Some(c) if self.quotes.contains(&c) => c,
+            Some(_) => return Err(ParseError::Fail("string not matched", start)),
+            None => return Err(ParseError::EOF),
+        };
 
-        // Consume digits
+        let mut s = String::new();
         loop {
-            match st.next() {
-                Some(c) if c.is_digit(10) => {
-                    if widebuf.is_none() {
-                        buf[i] = c;
-                        i += 1;
-                        if i >= BUFSIZE {
-                            widebuf = Some(buf.to_vec());
+            let ix = st.index();
+            let c = match st.next() {
+                Some(c) => c,
+                None => return Err(ParseError::Fail("unterminated string", start)),
+            };
+            if c == quote {
+                match hashes {
+                    None => return Ok(s),
+                    Some(n) => {
+                        let hold = st.hold();
+                        let mut seen = 0;
+                        while seen < n && st.peek() == Some('#') {
+                            st.next();
+                            seen += 1;
+                        }
+                        if seen == n {
+                            st.release(hold);
+                            return Ok(s);
                         }
-                    } else {
-                        widebuf.as_mut().unwrap().push(c);
-                        i += 1;
+                        st.reset(hold);
+                        s.push(c);
                     }
                 }
-                Some(_) => {
-                    st.undo_next();
-                    break;
+            } else if hashes.is_none() && Some(c) == self.escape {
+                match st.next() {
+                    Some('u') if self.utf16_escapes || self.braced_escapes => {
+                        s.push(self.parse_unicode(st, ix)?)
+                    }
+                    Some(e) => match self.table.iter().find(|(k, _)| *k == e) {
+                        Some((_, r)) => s.push(*r),
+                        None => return Err(ParseError::Fail("invalid escape sequence", ix)),
+                    },
+                    None => return Err(ParseError::Fail("unterminated string", start)),
                 }
-                None => break,
+            } else if !self.control_chars && c < '\u{20}' {
+                return Err(ParseError::Fail("control character in string", ix));
+            } else {
+                s.push(c);
             }
         }
-        if i == 0 {
-            st.reset(hold);
-            return Err(ParseError::Fail("no appropriate integer found", st.index()));
-        }
-        let intstr: String;
-        if widebuf.is_none() {
-            intstr = buf[..i].iter().collect();
-        } else {
-            intstr = widebuf.unwrap().iter().collect();
-        }
-        match IType::from_str(&intstr) {
-            Ok(i) => {
+    }
+}
+
+impl Parser for QuotedString {
+    type Result = String;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let hold = st.hold();
+        match self.parse_contents(st, start) {
+            Ok(s) => {
                 st.release(hold);
-                Ok(i)
+                Ok(s)
             }
             Err(e) => {
                 st.reset(hold);
-                Err(ParseError::ExecFail(e.description().to_string()))
+                Err(e)
             }
         }
     }
 }
 
-fn assemble_float(
-    s: Option<()>,
-    big: String,
-    dot: Option<String>,
-    mut little: Option<String>,
-    exp: Option<((), i32)>,
-) -> ParseResult<f64> {
-    if dot.is_some() && little.is_none() {
-        little = Some("0".to_string());
-    }
-    assert!((dot.is_some() && little.is_some()) || (dot.is_none() && little.is_none()));
-    let bigf = match f64::from_str(&big) {
-        Ok(f) => f,
-        Err(e) => return Err(execerr(e.description())),
-    };
-    let mut littlef = 0.;
-    if let Some(mut d) = dot {
-        d.push_str(little.as_ref().unwrap());
-        littlef = match f64::from_str(&d) {
-            Ok(f) => f,
-            Err(e) => return Err(execerr(e.description())),
-        }
-    }
-    let mut multiplier: f64 = if s.is_some() { -1. } else { 1. };
-    if let Some((_, e)) = exp {
-        multiplier *= (10. as f64).powi(e);
-    }
-    return Ok(multiplier * (bigf + littlef));
-}
+/// LineComment parses a comment starting with a prefix such as `//` or `#` and extending to the
+/// end of the line. The result is the comment's text without the prefix; the terminating newline
+/// is not consumed. Wrap it in `Ignore` to obtain `()` instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineComment(StringParser);
 
-/// float parses floats in the format of `[-]dd[.[dd]][e[-]ddd]`.
-///
-/// TODO: Compare speed with "native" parser, i.e. without combinators, and keep this as example.
-pub fn float() -> impl Parser<Result = f64> {
-    let digits_set = "0123456789";
-    let minus = Maybe::new(Ignore::new(StringParser::new("-")));
-    let digits = string_of(digits_set, RepeatSpec::Min(1));
-    let point = Maybe::new(StringParser::new("."));
-    let smalldigits = Maybe::new(string_of(digits_set, RepeatSpec::Min(1)));
-    let exp = Maybe::new(Sequence::new((
-        Ignore::new(StringParser::new("e")),
-        Int32::new(),
-    )));
-    let parser = Sequence::new((minus, digits, point, smalldigits, exp))
-        .apply(|(m, d, p, sd, exp)| assemble_float(m, d, p, sd, exp));
-    parser
+impl LineComment {
+    pub fn new<S: AsRef<str>>(prefix: S) -> LineComment {
+        LineComment(StringParser::new(prefix))
+    }
 }
 
-/// Nothing is a parser that always succeeds.
-pub struct Nothing;
-
-impl Parser for Nothing {
-    type Result = ();
+impl Parser for LineComment {
+    type Result = String;
     fn parse(
         &mut self,
-        _: &mut ParseState<impl Iterator<Item = char>>,
+        st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        Ok(())
+        self.0.parse(st)?;
+        let mut text = String::new();
+        while let Some(c) = st.peek() {
+            if c == '\n' {
+                break;
+            }
+            text.push(c);
+            st.next();
+        }
+        Ok(text)
     }
 }
 
-/// Efficient matching depending on number of characters (a HashSet is much slower than a Vec for
-/// less than ~20 chars).
-enum MatchSpec {
-    One(char),
-    Some(Vec<char>),
-    Many(HashSet<char>),
+/// BlockComment parses a comment enclosed in delimiters such as `/*` and `*/`. The result is the
+/// comment's text without the delimiters. Wrap it in `Ignore` to obtain `()` instead.
+///
+/// If the closing delimiter is missing, it fails with `Fail("unterminated comment", ix)`, `ix`
+/// being the position of the opening delimiter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockComment {
+    open: StringParser,
+    close: StringParser,
+    close_chars: Vec<char>,
+    nested: bool,
 }
 
-/// Threshold above which a HashSet is used instead of a Vec. Empirically determined.
-const MATCHSPEC_MANY_THRESHOLD: usize = 20;
+impl BlockComment {
+    /// Create a parser for comments between `open` and `close`, which end at the first
+    /// occurrence of `close`.
+    pub fn new<S: AsRef<str>>(open: S, close: S) -> BlockComment {
+        BlockComment {
+            open: StringParser::new(open.as_ref()),
+            close: StringParser::new(close.as_ref()),
+            close_chars: close.as_ref().chars().collect(),
+            nested: false,
+        }
+    }
+    /// Create a parser for comments that may contain nested comments, as in Rust or OCaml. The
+    /// text of nested comments, including their delimiters, is part of the result.
+    pub fn nested<S: AsRef<str>>(open: S, close: S) -> BlockComment {
+        BlockComment {
+            nested: true,
+            ..BlockComment::new(open, close)
+        }
+    }
 
-impl MatchSpec {
-    fn new<S: AsRef<str>>(chars: S) -> MatchSpec {
-        if chars.as_ref().len() == 1 {
-            MatchSpec::One(chars.as_ref().chars().next().unwrap())
-        } else if chars.as_ref().len() <= MATCHSPEC_MANY_THRESHOLD {
-            MatchSpec::Some(chars.as_ref().chars().collect())
-        } else {
-            MatchSpec::Many(chars.as_ref().chars().collect())
+    fn parse_nested(&mut self, st: &mut ParseState<impl Iterator<Item = char>>) -> Option<String> {
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            if let Ok(s) = self.close.parse(st) {
+                if depth == 0 {
+                    return Some(text);
+                }
+                depth -= 1;
+                text.push_str(&s);
+            } else if let Ok(s) = self.open.parse(st) {
+                depth += 1;
+                text.push_str(&s);
+            } else {
+                text.push(st.next()?);
+            }
         }
     }
-    fn matches(&self, c: char) -> bool {
-        match self {
-            MatchSpec::One(cc) => c == *cc,
-            MatchSpec::Some(cs) => cs.contains(&c),
-            MatchSpec::Many(cs) => cs.contains(&c),
+}
+
+impl Parser for BlockComment {
+    type Result = String;
+    fn parse(
+        &mut self,
+        st: &mut ParseState<impl Iterator<Item = char>>,
+    ) -> ParseResult<Self::Result> {
+        let start = st.index();
+        let hold = st.hold();
+        if let Err(e) = self.open.parse(st) {
+            st.reset(hold);
+            return Err(e);
+        }
+        let text = if self.nested {
+            self.parse_nested(st)
+        } else {
+            let close = &mut self.close;
+            st.take_until_literal(&self.close_chars)
+                .and_then(|t| close.parse(st).ok().map(|_| t))
+        };
+        match text {
+            Some(t) => {
+                st.release(hold);
+                Ok(t)
+            }
+            None => {
+                st.reset(hold);
+                Err(ParseError::Fail("unterminated comment", start))
+            }
         }
     }
 }
 
-/// OneOf matches any character that is (or is not) in its set.
-pub struct OneOf(MatchSpec, bool);
+/// Keyword matches a fixed word, but only if it is not immediately followed by a character that
+/// could continue the word (alphanumeric or `_`). For example, `Keyword::new("if")` matches the
+/// beginning of `if (x)` but not of `ifdef`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyword(StringParser);
 
-impl OneOf {
-    pub fn new<S: AsRef<str>>(chars: S) -> OneOf {
-        OneOf(MatchSpec::new(chars), false)
+impl Keyword {
+    pub fn new<S: AsRef<str>>(word: S) -> Keyword {
+        Keyword(StringParser::new(word))
     }
-    /// Create a OneOf parser that parses all characters *not* in the given set.
-    pub fn new_none_of<S: AsRef<str>>(chars: S) -> OneOf {
-        OneOf(MatchSpec::new(chars), true)
+    /// Create a Keyword parser ignoring case, like `StringParser::new_case_insensitive()`.
+    pub fn new_case_insensitive<S: AsRef<str>>(word: S) -> Keyword {
+        Keyword(StringParser::new_case_insensitive(word))
     }
 }
 
-impl Parser for OneOf {
-    type Result = char;
+impl Parser for Keyword {
+    type Result = String;
     fn parse(
         &mut self,
         st: &mut ParseState<impl Iterator<Item = char>>,
     ) -> ParseResult<Self::Result> {
-        match st.peek() {
-            Some(c) => {
-                if self.0.matches(c) ^ self.1 {
-                    st.next();
-                    Ok(c)
-                } else {
-                    Err(ParseError::Fail("char not matched", st.index()))
+        let hold = st.hold();
+        let start = st.index();
+        match self.0.parse(st) {
+            Ok(w) => match st.peek() {
+                Some(c) if c.is_alphanumeric() || c == '_' => {
+                    st.reset(hold);
+                    Err(ParseError::Fail("keyword not matched", start))
                 }
+                _ => {
+                    st.release(hold);
+                    Ok(w)
+                }
+            },
+            Err(e) => {
+                st.reset(hold);
+                Err(e)
             }
-            _ => Err(ParseError::EOF),
         }
     }
 }
 
-/// A parser that parses a string consisting of characters `chars`.
-pub fn string_of<S: AsRef<str>>(chars: S, rp: RepeatSpec) -> impl Parser<Result = String> {
-    let oo = OneOf::new(chars);
-    let rp = Repeat::new(oo, rp);
-    let make_string = |charvec: Vec<char>| Ok(String::from_iter(charvec.into_iter()));
-    rp.apply(make_string)
+/// Lexer creates parsers for tokens which skip the trivia (whitespace, comments) following them.
+/// It is configured once with a function returning a parser for trivia, e.g. `whitespace`:
+///
+/// ```
+/// use rcombinators::combinators::Sequence;
+/// use rcombinators::primitives::{whitespace, Int64, Lexer};
+/// use rcombinators::{ParseState, Parser};
+///
+/// let lex = Lexer::new(whitespace);
+/// let mut ps = ParseState::new("let x = 12 ;");
+/// let mut p = Sequence::new((
+///     lex.keyword("let"),
+///     lex.symbol("x"),
+///     lex.symbol("="),
+///     lex.lexeme(Int64::new()),
+///     lex.symbol(";"),
+/// ));
+/// assert_eq!(Ok(12), p.parse(&mut ps).map(|r| r.3));
+/// ```
+///
+/// Only trailing trivia is skipped; use `Lexer::trivia()` to skip trivia at the beginning of the
+/// input.
+pub struct Lexer<T: Parser, F: Fn() -> T> {
+    trivia: F,
 }
 
-/// A parser that parses a string consisting of any characters not in the set.
-pub fn string_none_of<S: AsRef<str>>(chars: S, rp: RepeatSpec) -> impl Parser<Result = String> {
-    let oo = OneOf::new_none_of(chars);
-    let rp = Repeat::new(oo, rp);
-    let make_string = |charvec: Vec<char>| Ok(String::from_iter(charvec.into_iter()));
-    rp.apply(make_string)
-}
+impl<T: Parser, F: Fn() -> T> Lexer<T, F> {
+    /// Create a Lexer using `trivia` to construct the parsers consuming trivia.
+    pub fn new(trivia: F) -> Lexer<T, F> {
+        Lexer { trivia }
+    }
 
-/// whitespace consumes any number of tabs, spaces, newlines.
-pub fn whitespace() -> impl Parser<Result = ()> {
-    Ignore::new(Repeat::new(OneOf::new(" \n\r\t"), RepeatSpec::Any))
+    /// Return a parser consuming trivia.
+    pub fn trivia(&self) -> T {
+        (self.trivia)()
+    }
+
+    /// Parse `p` and skip the trivia following it, returning `p`'s result.
+    pub fn lexeme<P: Parser>(&self, p: P) -> Terminated<P, T> {
+        terminated(p, self.trivia())
+    }
+
+    /// Parse the string `s`, and skip the trivia following it.
+    pub fn symbol<S: AsRef<str>>(&self, s: S) -> Terminated<StringParser, T> {
+        self.lexeme(StringParser::new(s))
+    }
+
+    /// Parse the keyword `word` (see `Keyword`), and skip the trivia following it.
+    pub fn keyword<S: AsRef<str>>(&self, word: S) -> Terminated<Keyword, T> {
+        self.lexeme(Keyword::new(word))
+    }
 }
 
 #[cfg(test)]
@@ -314,17 +1827,111 @@ mod tests {
     #[test]
     fn test_parse_string() {
         let mut s = ParseState::new("abc def");
-        let mut p = StringParser::new("abc ".to_owned());
+        let mut p = StringParser::new("abc ");
         assert_eq!(Ok("abc ".to_owned()), p.parse(&mut s));
         assert_eq!(4, s.index());
     }
 
+    #[test]
+    fn test_parse_string_case_insensitive() {
+        let mut s = ParseState::new("SeLeCt * FROM x");
+        let mut p = StringParser::new_ascii_case_insensitive("select");
+        assert_eq!(Ok("SeLeCt".to_owned()), p.parse(&mut s));
+        assert_eq!(6, s.index());
+        assert_eq!(
+            Err(ParseError::Fail("string not matched", 12)),
+            StringParser::new_ascii_case_insensitive(" * frox").parse(&mut s)
+        );
+        assert_eq!(6, s.index());
+        assert_eq!(
+            Ok(" * FROM".to_owned()),
+            StringParser::new_ascii_case_insensitive(" * from").parse(&mut s)
+        );
+        assert!(StringParser::new(" X").parse(&mut s).is_err());
+
+        let mut s = ParseState::new("ÄΣΑΣ\u{212A}ß");
+        assert!(StringParser::new_ascii_case_insensitive("ä")
+            .parse(&mut s)
+            .is_err());
+        let mut p = StringParser::new_case_insensitive("äσασk");
+        assert_eq!(Ok("ÄΣΑΣ\u{212A}".to_owned()), p.parse(&mut s));
+        assert!(StringParser::new_case_insensitive("SS")
+            .parse(&mut s)
+            .is_err());
+        assert_eq!(
+            Ok("ß".to_owned()),
+            StringParser::new_case_insensitive("ß").parse(&mut s)
+        );
+
+        // Only lower case mappings of single characters are used, which is stricter than case
+        // folding for some characters, but never matches different letters.
+        for (input, want) in [("ı", "i"), ("İ", "i"), ("ς", "σ"), ("ﬀ", "FF")].iter() {
+            assert!(StringParser::new_case_insensitive(want)
+                .parse(&mut ParseState::new(input))
+                .is_err());
+        }
+        assert!(StringParser::new_case_insensitive("I")
+            .parse(&mut ParseState::new("ı"))
+            .is_err());
+
+        let mut s = ParseState::new("WHERE WHEREVER");
+        let mut p = Keyword::new_case_insensitive("where");
+        assert_eq!(Ok("WHERE".to_owned()), p.parse(&mut s));
+        s.next();
+        assert!(p.parse(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_literals() {
+        let mut p = Literals::new(vec![("if", 1), ("ifdef", 2), ("in", 3), ("i", 4)]);
+        let mut st = ParseState::new("ifde ifdef inx if");
+        assert_eq!(Ok(1), p.parse(&mut st));
+        assert_eq!(2, st.index());
+        assert_eq!(
+            Err(ParseError::Fail("no literal matched", 2)),
+            p.parse(&mut st)
+        );
+        st.next();
+        st.next();
+        st.next();
+        assert_eq!(Ok(2), p.parse(&mut st));
+        st.next();
+        assert_eq!(Ok(3), p.parse(&mut st));
+        assert_eq!(Ok(4), p.parse(&mut ParseState::new("ix")));
+        p.insert("if", 5);
+        p.insert("", 0);
+        assert_eq!(Ok(0), p.parse(&mut st));
+        st.next();
+        st.next();
+        assert_eq!(Ok(5), p.parse(&mut st));
+
+        let mut p = Literals::new_case_insensitive(vec![("Select", "select"), ("SET", "set")]);
+        let mut st = ParseState::new("sElEcTsEt");
+        assert_eq!(Ok("select"), p.parse(&mut st));
+        assert_eq!(Ok("set"), p.parse(&mut st));
+        assert!(p.parse(&mut ParseState::new("SELECT")).is_ok());
+        assert!(p.parse(&mut ParseState::new("seLect")).is_ok());
+        assert!(p.parse(&mut ParseState::new("ſet")).is_err());
+        let mut p = Literals::new_case_insensitive(vec![("if", 1)]);
+        assert!(p.parse(&mut ParseState::new("ıf")).is_err());
+
+        // Many literals, with matches spanning buffer refills.
+        let words: Vec<String> = (0..1000).map(|i| format!("w{}", i * 7)).collect();
+        let mut p = Literals::new(words.iter().map(|w| (w, w.len())));
+        let input = words.join(" ");
+        let mut st = ParseState::from_reader(input.as_bytes());
+        for w in words.iter() {
+            assert_eq!(Ok(w.len()), p.parse(&mut st));
+            st.next();
+        }
+    }
+
     #[test]
     fn test_parse_int() {
         let mut s = ParseState::new("-1252 353 354 -1253 422345");
         let mut ip = Int64::new();
         let mut up = Uint64::new();
-        let mut sp = StringParser::new(" ".to_string());
+        let mut sp = StringParser::new(" ");
         assert_eq!(Ok(-1252), ip.parse(&mut s));
         assert_eq!(Ok(" ".to_string()), sp.parse(&mut s));
         assert_eq!(Ok(353), ip.parse(&mut s));
@@ -337,18 +1944,74 @@ mod tests {
         assert_eq!(Ok(422345), up.parse(&mut s));
     }
 
+    #[test]
+    fn test_parse_int_radix() {
+        let parse = |p: &mut Int64, s: &str| p.parse(&mut ParseState::new(s));
+        let mut p = Int64::new().radix(16);
+        assert_eq!(Ok(-0xff), parse(&mut p, "-fF"));
+        assert_eq!(Ok(0), parse(&mut p, "0x10"));
+
+        let mut p = Int64::new().prefixed().allow_plus();
+        assert_eq!(Ok(255), parse(&mut p, "0xff"));
+        assert_eq!(Ok(-8), parse(&mut p, "-0o10"));
+        assert_eq!(Ok(5), parse(&mut p, "+0b101"));
+        assert_eq!(Ok(10), parse(&mut p, "10"));
+        let mut st = ParseState::new("0b2");
+        assert_eq!(Ok(0), p.parse(&mut st));
+        assert_eq!(1, st.index());
+        assert_eq!(
+            Err(ParseError::Fail("no appropriate integer found", 1)),
+            parse(&mut p, "+x")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("not start of integer", 0)),
+            parse(&mut Int64::new(), "+1")
+        );
+
+        let mut p = Int64::new().separator(',');
+        let mut st = ParseState::new("1,000,000, 1,,2");
+        assert_eq!(Ok(1000000), p.parse(&mut st));
+        assert_eq!(9, st.index());
+        st.next();
+        st.next();
+        assert_eq!(Ok(1), p.parse(&mut st));
+        assert_eq!(12, st.index());
+
+        assert_eq!(
+            Err(ParseError::Fail("integer too large", 0)),
+            Uint8::new().parse(&mut ParseState::new("256"))
+        );
+        assert_eq!(
+            Err(ParseError::Fail("integer too small", 0)),
+            Int8::new().parse(&mut ParseState::new("-129"))
+        );
+        assert_eq!(Ok(-128), Int8::new().parse(&mut ParseState::new("-128")));
+        let mut st = ParseState::new("x0ffffffff");
+        st.next();
+        assert_eq!(
+            Err(ParseError::Fail("integer too large", 1)),
+            Int32::new().radix(16).parse(&mut st)
+        );
+        assert_eq!(1, st.index());
+    }
+
     #[test]
     fn test_parse_long_int() {
         let mut s = ParseState::new("123456789");
         let mut up = Uint128::new();
-        assert_eq!(Ok(123456789 as u128), up.parse(&mut s));
+        assert_eq!(Ok(123456789_u128), up.parse(&mut s));
+        let mut s = ParseState::new("-170141183460469231731687303715884105728");
+        assert_eq!(Ok(i128::MIN), Int128::new().parse(&mut s));
+        let ones = "1".repeat(64);
+        let mut s = ParseState::new(&ones);
+        assert_eq!(Ok(u64::MAX), Uint64::new().radix(2).parse(&mut s));
     }
 
     #[test]
     fn test_parse_floats() {
         let mut ps = ParseState::new("1 1. 1.5 -1.5 -1.75 2.5e-4 -2e-2");
         let mut p = float();
-        let want = vec![1., 1., 1.5, -1.5, -1.75, 2.5e-4, -0.02];
+        let want = [1., 1., 1.5, -1.5, -1.75, 2.5e-4, -0.02];
         for &f in want.iter() {
             assert_eq!(Ok(f), p.parse(&mut ps));
             let _ = StringParser::new(" ").parse(&mut ps);
@@ -369,14 +2032,400 @@ mod tests {
         assert_eq!(Ok("aaabc".to_string()), p.parse(&mut st));
     }
 
-    use std::iter;
+    #[test]
+    fn test_char_classes() {
+        let mut st = ParseState::new("abXYß٣9 \u{2003}x_1+");
+        assert_eq!(
+            Ok("ab".to_string()),
+            string_of('a'..='z', RepeatSpec::Any).parse(&mut st)
+        );
+        assert_eq!(
+            Ok("XYß".to_string()),
+            string_of(CharClass::Alphabetic, RepeatSpec::Any).parse(&mut st)
+        );
+        assert_eq!(Ok('٣'), OneOf::new(CharClass::Numeric).parse(&mut st));
+        assert!(OneOf::new(CharClass::Alphabetic).parse(&mut st).is_err());
+        assert_eq!(Ok('9'), satisfy(|c| c.is_ascii_digit()).parse(&mut st));
+        assert_eq!(
+            Ok(" \u{2003}".to_string()),
+            string_of(CharClass::Whitespace, RepeatSpec::Any).parse(&mut st)
+        );
+        assert_eq!(Ok('x'), OneOf::new(CharClass::XidStart).parse(&mut st));
+        assert!(OneOf::new(CharClass::XidStart).parse(&mut st).is_err());
+        assert_eq!(
+            Ok("_1".to_string()),
+            string_of(CharClass::XidContinue, RepeatSpec::Any).parse(&mut st)
+        );
+        let spec = MatchSpec::from('+').or(CharClass::Uppercase).or("-*");
+        assert!(spec.matches('+') && spec.matches('Q') && spec.matches('*'));
+        assert!(!spec.matches('q'));
+        assert_eq!(Ok('+'), OneOf::new(spec).parse(&mut st));
+
+        let mut st = ParseState::new("äöü!");
+        assert_eq!(Ok('ä'), OneOf::new("ä").parse(&mut st));
+        assert_eq!(
+            Ok("öü".to_string()),
+            string_none_of(String::from("!"), RepeatSpec::Any).parse(&mut st)
+        );
+    }
+
+    #[test]
+    fn test_identifier() {
+        let mut st = ParseState::new("_a1 if 1a ifx äb");
+        let mut p = identifier().reserved(&["if", "else"]);
+        assert_eq!(Ok("_a1".to_string()), p.parse(&mut st));
+        st.next();
+        assert_eq!(
+            Err(ParseError::Fail("keyword used as identifier", 4)),
+            p.parse(&mut st)
+        );
+        assert_eq!(4, st.index());
+        assert_eq!(Ok("if".to_string()), Keyword::new("if").parse(&mut st));
+        st.next();
+        assert_eq!(
+            Err(ParseError::Fail("not start of identifier", 7)),
+            p.parse(&mut st)
+        );
+        st.next();
+        assert_eq!(Ok("a".to_string()), p.parse(&mut st));
+        st.next();
+        assert_eq!(Ok("ifx".to_string()), p.parse(&mut st));
+        st.next();
+        assert!(p.parse(&mut st).is_err());
+        assert_eq!(Ok("äb".to_string()), Identifier::unicode().parse(&mut st));
+        assert_eq!(Err(ParseError::EOF), p.parse(&mut st));
+
+        let mut p = Identifier::new('$', CharClass::AsciiDigit);
+        assert_eq!(Ok("$12".to_string()), p.parse(&mut ParseState::new("$12a")));
+    }
+
+    #[test]
+    fn test_take_until() {
+        let mut st = ParseState::new("/* a * b **/ x");
+        let mut p = Sequence::new((
+            StringParser::new("/*"),
+            take_until("*/"),
+            StringParser::new("*/"),
+        ));
+        assert_eq!(
+            Ok(("/*".to_string(), " a * b *".to_string(), "*/".to_string())),
+            p.parse(&mut st)
+        );
+        assert!(take_until("*/").parse(&mut st).is_err());
+        assert_eq!(Ok(" ".to_string()), take_until("x").parse(&mut st));
+        assert_eq!(Ok("".to_string()), take_until("x").parse(&mut st));
+
+        // Terminators spanning buffer refills are found as well.
+        let input = format!("{}END", "a".repeat(1023));
+        let mut st = ParseState::from_reader(input.as_bytes());
+        assert_eq!(Ok(1023), take_until("END").parse(&mut st).map(|s| s.len()));
+    }
+
+    #[test]
+    fn test_lexer() {
+        let lex = Lexer::new(whitespace);
+        let mut st = ParseState::new("if  ifdef (x) iffy");
+        assert_eq!(Ok("if".to_string()), lex.keyword("if").parse(&mut st));
+        assert_eq!(4, st.index());
+        assert_eq!(
+            Err(ParseError::Fail("keyword not matched", 4)),
+            lex.keyword("if").parse(&mut st)
+        );
+        assert_eq!(4, st.index());
+        assert_eq!(Ok("ifdef".to_string()), lex.keyword("ifdef").parse(&mut st));
+        let mut p = Sequence::new((
+            lex.symbol("("),
+            lex.lexeme(OneOf::new("xyz")),
+            lex.symbol(")"),
+        ));
+        assert_eq!(
+            Ok(("(".to_string(), 'x', ")".to_string())),
+            p.parse(&mut st)
+        );
+        assert_eq!(Ok("iff".to_string()), lex.symbol("iff").parse(&mut st));
+        assert_eq!(Ok("y".to_string()), lex.keyword("y").parse(&mut st));
+        assert_eq!(Ok(()), lex.trivia().parse(&mut st));
+    }
+
+    #[test]
+    fn test_quoted_string() {
+        let parse = |p: &mut QuotedString, s: &str| p.parse(&mut ParseState::new(s));
+        let mut p = QuotedString::new();
+        assert_eq!(Ok("".to_string()), parse(&mut p, r#""""#));
+        assert_eq!(
+            Ok("a\"b\\\n\t/'".to_string()),
+            parse(&mut p, r#""a\"b\\\n\t\/\'""#)
+        );
+        assert_eq!(Ok("äx".to_string()), parse(&mut p, r#""\u00E4\u{78}""#));
+        assert_eq!(Ok("😀".to_string()), parse(&mut p, r#""\ud83d\uDE00""#));
+        assert_eq!(Ok("😀".to_string()), parse(&mut p, r#""\u{1F600}""#));
+        assert_eq!(
+            Err(ParseError::Fail("invalid escape sequence", 2)),
+            parse(&mut p, r#""a\qb""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("invalid unicode escape", 1)),
+            parse(&mut p, r#""\u12x4""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("invalid unicode escape", 1)),
+            parse(&mut p, r#""\u{d800}""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("unpaired surrogate in unicode escape", 2)),
+            parse(&mut p, r#""x\ud83d""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("unpaired surrogate in unicode escape", 1)),
+            parse(&mut p, r#""\ude00\ud83d""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("unterminated string", 0)),
+            parse(&mut p, r#""abc\""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("string not matched", 0)),
+            parse(&mut p, "'abc'")
+        );
+
+        let mut st = ParseState::new(r#"'a"b' "c\'" x"#);
+        let mut p = QuotedString::new().quotes("'\"").without_escapes();
+        assert_eq!(Ok("a\"b".to_string()), p.parse(&mut st));
+        st.next();
+        assert_eq!(Ok("c\\'".to_string()), p.parse(&mut st));
+        st.next();
+        assert!(p.parse(&mut st).is_err());
+        assert_eq!(12, st.index());
+
+        let mut p = QuotedString::new().raw_strings('r');
+        assert_eq!(Ok("a\\n".to_string()), parse(&mut p, r#"r"a\n""#));
+        assert_eq!(Ok("a\"#b".to_string()), parse(&mut p, r###"r##"a"#b"##"###));
+        assert_eq!(Ok("\n".to_string()), parse(&mut p, r#""\n""#));
+        let mut p = QuotedString::new()
+            .escape_char('%')
+            .escape_table(&[('%', '%')]);
+        assert_eq!(Ok("%\\".to_string()), parse(&mut p, r#""%%\""#));
+
+        let mut p = QuotedString::json();
+        assert_eq!(Ok("ä/\"".to_string()), parse(&mut p, r#""\u00e4\/\"""#));
+        assert_eq!(
+            Err(ParseError::Fail("control character in string", 2)),
+            parse(&mut p, "\"a\nb\"")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("control character in string", 1)),
+            parse(&mut p, "\"\tb\"")
+        );
+        assert_eq!(Ok("a\nb\t".to_string()), parse(&mut p, r#""a\nb\t""#));
+        assert_eq!(
+            Ok("a\nb".to_string()),
+            parse(&mut QuotedString::new(), "\"a\nb\"")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("invalid unicode escape", 1)),
+            parse(&mut p, r#""\u{41}""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("invalid escape sequence", 1)),
+            parse(&mut p, r#""\'""#)
+        );
+        assert_eq!(
+            Err(ParseError::Fail("invalid escape sequence", 1)),
+            parse(&mut p, r#""\0""#)
+        );
+        let mut p = QuotedString::new().utf16_escapes(false);
+        assert_eq!(Ok("A".to_string()), parse(&mut p, r#""\u{41}""#));
+        assert_eq!(
+            Err(ParseError::Fail("invalid unicode escape", 1)),
+            parse(&mut p, r#""\u0041""#)
+        );
+        let mut p = QuotedString::new()
+            .utf16_escapes(false)
+            .braced_escapes(false);
+        assert_eq!(
+            Err(ParseError::Fail("invalid escape sequence", 1)),
+            parse(&mut p, r#""\u0041""#)
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut st = ParseState::new("// line\n# x\n/* a /* b */ c */");
+        assert_eq!(
+            Ok(" line".to_string()),
+            LineComment::new("//").parse(&mut st)
+        );
+        assert_eq!(7, st.index());
+        assert!(LineComment::new("#").parse(&mut st).is_err());
+        st.next();
+        assert_eq!(Ok(" x".to_string()), LineComment::new("#").parse(&mut st));
+        st.next();
+        let mut p = BlockComment::new("/*", "*/");
+        let mut nested = BlockComment::nested("/*", "*/");
+        let hold = st.hold();
+        assert_eq!(Ok(" a /* b ".to_string()), p.parse(&mut st));
+        st.reset(hold);
+        assert_eq!(Ok(" a /* b */ c ".to_string()), nested.parse(&mut st));
+        assert_eq!(None, st.peek());
+
+        // Unterminated comments are reported at the opening delimiter.
+        let mut st = ParseState::new("x (* a (* b *)");
+        st.next();
+        st.next();
+        assert_eq!(
+            Err(ParseError::Fail("unterminated comment", 2)),
+            BlockComment::nested("(*", "*)").parse(&mut st)
+        );
+        assert_eq!(2, st.index());
+        assert_eq!(
+            Ok(" a (* b ".to_string()),
+            BlockComment::new("(*", "*)").parse(&mut st)
+        );
+        let mut st = ParseState::new("/* a");
+        assert_eq!(
+            Err(ParseError::Fail("unterminated comment", 0)),
+            BlockComment::new("/*", "*/").parse(&mut st)
+        );
+    }
+
+    #[test]
+    fn test_parse_native_floats() {
+        let parse = |p: &mut Float64, s: &str| {
+            let mut st = ParseState::new(s);
+            p.parse(&mut st).map(|f| (f, st.index()))
+        };
+        let mut p = Float64::new();
+        for &s in [
+            "1",
+            "-1.5",
+            "+1.0",
+            ".5",
+            "5.",
+            "1E5",
+            "-2.5e-4",
+            "1e+3",
+            "0.1",
+            "1.7976931348623157e308",
+            "2.2250738585072014e-308",
+            "123456789012345678901234567890",
+            "0.30000000000000004",
+        ]
+        .iter()
+        {
+            assert_eq!(Ok((f64::from_str(s).unwrap(), s.len())), parse(&mut p, s));
+        }
+        assert_eq!(Ok((f64::INFINITY, 3)), parse(&mut p, "inf"));
+        assert_eq!(Ok((f64::NEG_INFINITY, 9)), parse(&mut p, "-Infinity"));
+        assert!(parse(&mut p, "NaN").unwrap().0.is_nan());
+        assert_eq!(Ok((1., 1)), parse(&mut p, "1e"));
+        assert_eq!(Ok((1., 2)), parse(&mut p, "1.e-"));
+        assert_eq!(
+            Err(ParseError::Fail("not start of float", 0)),
+            parse(&mut p, "-.e1")
+        );
+        assert_eq!(Err(ParseError::EOF), parse(&mut p, ""));
+
+        let mut p = Float64::json();
+        assert_eq!(Ok((-0.0, 2)), parse(&mut p, "-0"));
+        assert_eq!(Ok((0., 1)), parse(&mut p, "01"));
+        assert_eq!(Ok((0.05, 4)), parse(&mut p, "0.05"));
+        assert_eq!(Ok((1., 1)), parse(&mut p, "1."));
+        assert_eq!(Ok((1.5e10, 7)), parse(&mut p, "1.5E+10"));
+        assert!(parse(&mut p, "+1").is_err());
+        assert!(parse(&mut p, ".5").is_err());
+        assert!(parse(&mut p, "inf").is_err());
+
+        let mut p = Float64::rust();
+        assert_eq!(Ok((1000.5, 7)), parse(&mut p, "1_000.5"));
+        assert_eq!(Ok((1., 1)), parse(&mut p, "1_"));
+        assert_eq!(Ok((5., 2)), parse(&mut p, "5."));
+        assert!(parse(&mut p, ".5").is_err());
+
+        let mut st = ParseState::new("3.4028236e38 0.1");
+        let mut p = Float32::c();
+        assert_eq!(Ok(f32::INFINITY), p.parse(&mut st));
+        st.next();
+        assert_eq!(Ok(0.1_f32), p.parse(&mut st));
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        let parse = |s: &str| DecimalParser::<Decimal>::new().parse(&mut ParseState::new(s));
+        assert_eq!(Ok(Decimal::new(false, "150", -2)), parse("1.50"));
+        assert_eq!(Ok(Decimal::new(true, "5", -1)), parse("-.5"));
+        assert_eq!(Ok(Decimal::new(false, "0", 0)), parse("000"));
+        assert_eq!(Ok(Decimal::new(false, "12", 3)), parse("+12e3"));
+        assert_eq!(Ok(Decimal::new(false, "125", -4)), parse("1.25E-2"));
+        assert_eq!(
+            Ok(Decimal::new(false, "1234567890123456789012345678901", -30)),
+            parse("1.234567890123456789012345678901")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("exponent too large", 0)),
+            parse("1e99999999999999999999")
+        );
+        assert_eq!(
+            Ok(Decimal::new(false, "0", 0)),
+            parse("0e99999999999999999999")
+        );
+        assert_eq!(
+            Ok(Decimal::new(true, "0", 0)),
+            parse("-0.0e-9223372036854775808")
+        );
+        assert!(parse("inf").is_err());
+
+        for (d, s) in [
+            (Decimal::new(false, "150", -2), "1.50"),
+            (Decimal::new(true, "5", -3), "-0.005"),
+            (Decimal::new(false, "5", -30), "5e-30"),
+            (Decimal::new(false, "12", 3), "12e3"),
+            (Decimal::new(false, "12", 0), "12"),
+        ]
+        .iter()
+        {
+            assert_eq!(*s, d.to_string());
+        }
+
+        let mut p = DecimalParser::<i64>::with_syntax(FloatSyntax::json());
+        let mut parse = |s: &str| p.parse(&mut ParseState::new(s));
+        assert_eq!(Ok(1500), parse("1.5e3"));
+        assert_eq!(Ok(-15), parse("-150e-1"));
+        assert_eq!(Ok(0), parse("0.000e-999"));
+        assert_eq!(Err(ParseError::Fail("not an integer", 0)), parse("1.5"));
+        assert_eq!(Err(ParseError::Fail("not an integer", 0)), parse("1e-100"));
+        assert_eq!(Err(ParseError::Fail("integer too large", 0)), parse("1e19"));
+        assert_eq!(
+            Err(ParseError::Fail("integer too large", 0)),
+            parse("1e9223372036854775807")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("not an integer", 0)),
+            parse("1e-9223372036854775808")
+        );
+        assert_eq!(
+            Err(ParseError::Fail("integer too small", 0)),
+            parse("-1e100")
+        );
+
+        let mut p = DecimalParser::<u8>::new();
+        let mut parse = |s: &str| p.parse(&mut ParseState::new(s));
+        assert_eq!(Ok(0), parse("-0"));
+        assert_eq!(Ok(0), parse("-0.0e5"));
+        assert_eq!(Err(ParseError::Fail("integer too small", 0)), parse("-1"));
+        assert_eq!(Err(ParseError::Fail("not an integer", 0)), parse("-1.5"));
+        assert_eq!(Ok(255), parse("2.55e2"));
+
+        assert_eq!(
+            Ok(0.1 + 0.2),
+            DecimalParser::<f64>::new().parse(&mut ParseState::new("0.30000000000000004"))
+        );
+    }
 
     #[test]
     fn bench_integer_medium() {
         let piece = "-422345812310928 ";
         let repeats = 1000000;
-        let mut input = String::with_capacity(piece.len() * repeats);
-        input.extend(iter::repeat(piece).take(repeats));
+        let input = piece.repeat(repeats);
         let mut ps = ParseState::new(&input);
         let mut p = Sequence::new((Int64::new(), StringParser::new(" ")));
         {
@@ -387,8 +2436,7 @@ mod tests {
         }
 
         let piece = "-4223458123109289 ";
-        let mut input = String::with_capacity(piece.len() * repeats);
-        input.extend(iter::repeat(piece).take(repeats));
+        let input = piece.repeat(repeats);
         let mut ps = ParseState::new(&input);
         {
             time_test!("parse-int with dynamic buffer");
@@ -402,8 +2450,7 @@ mod tests {
     fn bench_float() {
         let piece = "-32.334 ";
         let repeats = 1000000;
-        let mut input = String::with_capacity(piece.len() * repeats);
-        input.extend(iter::repeat(piece).take(repeats));
+        let input = piece.repeat(repeats);
         let mut ps = ParseState::new(&input);
         let mut p = Sequence::new((float(), StringParser::new(" ")));
         {
@@ -412,5 +2459,14 @@ mod tests {
                 let _ = p.parse(&mut ps);
             }
         }
+
+        let mut ps = ParseState::new(&input);
+        let mut p = Sequence::new((Float64::new(), StringParser::new(" ")));
+        {
+            time_test!("parse-float natively");
+            for _ in 0..repeats {
+                let _ = p.parse(&mut ps);
+            }
+        }
     }
 }
@@ -0,0 +1,145 @@
+use std::fmt;
+
+/// Location describes a position in terms of the source it came from: the source's name, and
+/// line and column (both starting at 1) within it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location<'a> {
+    /// Name of the source, as given to `ParseState::include()` or `ParseState::set_source_name()`.
+    pub source: &'a str,
+    /// Line in the source, starting at 1.
+    pub line: usize,
+    /// Column in the line, starting at 1 and counted in chars.
+    pub column: usize,
+    /// Offset in chars from the beginning of the source.
+    pub offset: usize,
+}
+
+impl<'a> fmt::Display for Location<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+struct Source {
+    name: String,
+    // Offsets (within the source) at which lines start.
+    line_starts: Vec<usize>,
+    // Number of chars seen so far.
+    len: usize,
+}
+
+impl Source {
+    fn new(name: String) -> Source {
+        Source {
+            name,
+            line_starts: vec![0],
+            len: 0,
+        }
+    }
+    fn feed(&mut self, chars: &[char]) {
+        for &c in chars {
+            self.len += 1;
+            if c == '\n' {
+                self.line_starts.push(self.len);
+            }
+        }
+    }
+}
+
+/// A Segment maps global indices starting at `start` (up to the next segment's `start`) to the
+/// source `source`, beginning at `offset` within it.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    start: usize,
+    source: usize,
+    offset: usize,
+}
+
+/// SourceMap translates global indices in a `ParseState` into locations within the original
+/// sources. Source 0 is the one read by the `ParseState` itself, all other ones have been spliced
+/// in using `ParseState::include()`.
+#[derive(Debug)]
+pub(crate) struct SourceMap {
+    sources: Vec<Source>,
+    // Sorted by `start`; the first segment always starts at 0.
+    segments: Vec<Segment>,
+}
+
+impl SourceMap {
+    pub(crate) fn new() -> SourceMap {
+        SourceMap {
+            sources: vec![Source::new("<input>".to_string())],
+            segments: vec![Segment {
+                start: 0,
+                source: 0,
+                offset: 0,
+            }],
+        }
+    }
+
+    pub(crate) fn set_name<S: AsRef<str>>(&mut self, name: S) {
+        self.sources[0].name = name.as_ref().to_string();
+    }
+
+    /// Register chars read from the main source.
+    pub(crate) fn feed(&mut self, chars: &[char]) {
+        self.sources[0].feed(chars);
+    }
+
+    /// Register `content` from the source `name` as inserted at global index `at`.
+    pub(crate) fn splice<S: AsRef<str>>(&mut self, at: usize, name: S, content: &[char]) {
+        if content.is_empty() {
+            return;
+        }
+        let mut source = Source::new(name.as_ref().to_string());
+        source.feed(content);
+        self.sources.push(source);
+
+        let k = self.segment_at(at);
+        let seg = self.segments[k];
+        let inserted = Segment {
+            start: at,
+            source: self.sources.len() - 1,
+            offset: 0,
+        };
+        let rest = Segment {
+            start: at + content.len(),
+            source: seg.source,
+            offset: seg.offset + (at - seg.start),
+        };
+        for s in self.segments[k + 1..].iter_mut() {
+            s.start += content.len();
+        }
+        if at == seg.start {
+            self.segments[k] = inserted;
+            self.segments.insert(k + 1, rest);
+        } else {
+            self.segments.insert(k + 1, inserted);
+            self.segments.insert(k + 2, rest);
+        }
+    }
+
+    /// Return the name of the source at global index `ix`.
+    pub(crate) fn source_name(&self, ix: usize) -> &str {
+        &self.sources[self.segments[self.segment_at(ix)].source].name
+    }
+
+    /// Translate global index `ix` into a location.
+    pub(crate) fn locate(&self, ix: usize) -> Location<'_> {
+        let seg = self.segments[self.segment_at(ix)];
+        let source = &self.sources[seg.source];
+        let offset = seg.offset + (ix - seg.start);
+        let line = source.line_starts.partition_point(|&l| l <= offset);
+        Location {
+            source: &source.name,
+            line,
+            column: offset - source.line_starts[line - 1] + 1,
+            offset,
+        }
+    }
+
+    fn segment_at(&self, ix: usize) -> usize {
+        self.segments.partition_point(|s| s.start <= ix) - 1
+    }
+}
@@ -1,7 +1,11 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
 use std::io;
 use std::str::Chars;
 
-use utf8reader;
+use crate::parser::ParseResult;
+use crate::source::{Location, SourceMap};
 
 struct UTF8Reader<R: io::Read>(utf8reader::UTF8Reader<R>);
 
@@ -18,7 +22,67 @@ impl<R: io::Read> Iterator for UTF8Reader<R> {
     }
 }
 
+/// UserState is implemented by all types that can be stored in a `ParseState` using
+/// `ParseState::set_user_state()`.
+trait UserState: Any {
+    fn clone_box(&self) -> Box<dyn UserState>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<S: Any + Clone> UserState for S {
+    fn clone_box(&self) -> Box<dyn UserState> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// UserSlot holds the user state of a `ParseState`.
+struct UserSlot {
+    state: Box<dyn UserState>,
+    // Whether holds snapshot the state, so that it is restored on reset.
+    rollback: bool,
+}
+
+impl fmt::Debug for UserSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UserSlot")
+            .field("rollback", &self.rollback)
+            .finish()
+    }
+}
+
+/// MemoKey identifies a memoizing parser in the memo table of a `ParseState`. See
+/// `combinators::Memo`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub(crate) enum MemoKey {
+    /// A single parser instance.
+    Id(usize),
+    /// All parsers sharing a name.
+    Name(&'static str),
+}
+
+/// A cached parse result, along with the index at which the parser stopped.
+#[derive(Debug)]
+struct MemoEntry {
+    end: usize,
+    // A ParseResult<R> with R being the memoized parser's result type.
+    result: Box<dyn Any>,
+}
+
 /// ParseState encapsulates a stream of chars.
+///
+/// In addition to the input, a ParseState can carry arbitrary user data (for example symbol tables
+/// or nesting depth for context-sensitive grammars); see `set_user_state()`.
 #[derive(Debug)]
 pub struct ParseState<Iter: Iterator<Item = char>> {
     buf: Vec<char>,
@@ -30,6 +94,14 @@ pub struct ParseState<Iter: Iterator<Item = char>> {
     current: usize,
     // Smallest held index, count of how many holds refer to it.
     oldest_hold_count: Option<(usize, usize)>,
+    // Maps global indices to the sources they were read from.
+    sources: SourceMap,
+    // User-defined state.
+    user: Option<UserSlot>,
+    // Columns of the enclosing indented blocks.
+    indents: Vec<usize>,
+    // Results of memoizing parsers by start index.
+    memo: HashMap<(MemoKey, usize), MemoEntry>,
 }
 
 /// A Hold represents the parsing state at a certain point. It can be used to "un-consume" input.
@@ -38,13 +110,16 @@ pub struct ParseState<Iter: Iterator<Item = char>> {
 pub struct Hold {
     ix: usize,
     released: bool,
+    // Snapshot of the user state if it is to be rolled back.
+    user: Option<Box<dyn UserState>>,
 }
 
 impl Hold {
-    fn new(ix: usize) -> Hold {
+    fn new(ix: usize, user: Option<Box<dyn UserState>>) -> Hold {
         Hold {
-            ix: ix,
+            ix,
             released: false,
+            user,
         }
     }
     fn defuse(&mut self) {
@@ -69,6 +144,10 @@ impl<'a> ParseState<Chars<'a>> {
             current: 0,
             global: 0,
             oldest_hold_count: None,
+            sources: SourceMap::new(),
+            user: None,
+            indents: vec![],
+            memo: HashMap::new(),
         }
     }
     /// Initialize ParseState from a UTF-8 encoded source.
@@ -79,6 +158,10 @@ impl<'a> ParseState<Chars<'a>> {
             current: 0,
             global: 0,
             oldest_hold_count: None,
+            sources: SourceMap::new(),
+            user: None,
+            indents: vec![],
+            memo: HashMap::new(),
         }
     }
 }
@@ -94,6 +177,159 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
         self.global
     }
 
+    /// Set the name of the source this `ParseState` was created from, as used in `Location`s. The
+    /// default is `<input>`.
+    pub fn set_source_name<S: AsRef<str>>(&mut self, name: S) {
+        self.sources.set_name(name);
+    }
+
+    /// Splice `content` into the input at the current position. Subsequent parsing continues with
+    /// `content`, and returns to the original input once it is exhausted. This is useful for
+    /// implementing `include`-like directives; `name` is used in `Location`s referring to
+    /// `content`.
+    ///
+    /// Global indices (as returned by `index()`) continue to increase monotonically across
+    /// included content; use `location()` to translate them back to a source, line and column.
+    ///
+    /// Note that resetting to a `Hold` created before the call to `include()` does not remove the
+    /// included content again.
+    pub fn include<N: AsRef<str>, C: AsRef<str>>(&mut self, name: N, content: C) {
+        let chars: Vec<char> = content.as_ref().chars().collect();
+        self.sources.splice(self.global, name, &chars);
+        self.buf.splice(self.current..self.current, chars);
+        // Cached results may depend on input after this position, even if they end before it
+        // (lookahead, failed alternatives), so none of them can be trusted anymore.
+        self.memo.clear();
+    }
+
+    /// Store user-defined state in the ParseState, replacing any previous state. It can be accessed
+    /// by parsers using `user_state()` and `user_state_mut()`, or by closures using
+    /// `Parser::apply_with_state()`.
+    ///
+    /// If `rollback` is true, resetting a `Hold` also restores the state to what it was when the
+    /// hold was created. This keeps the state consistent when backtracking (e.g. in an
+    /// `Alternative`), but requires cloning the state on every hold; prefer state that is cheap to
+    /// clone in that case.
+    pub fn set_user_state<S: Any + Clone>(&mut self, state: S, rollback: bool) {
+        self.user = Some(UserSlot {
+            state: Box::new(state),
+            rollback,
+        });
+    }
+
+    /// Return a reference to the user state, if it has been set and is of type `S`.
+    pub fn user_state<S: Any>(&self) -> Option<&S> {
+        self.user
+            .as_ref()
+            .and_then(|slot| slot.state.as_any().downcast_ref())
+    }
+
+    /// Return a mutable reference to the user state, if it has been set and is of type `S`.
+    pub fn user_state_mut<S: Any>(&mut self) -> Option<&mut S> {
+        self.user
+            .as_mut()
+            .and_then(|slot| slot.state.as_any_mut().downcast_mut())
+    }
+
+    /// Remove the user state from the ParseState and return it, if it is of type `S`.
+    pub fn take_user_state<S: Any>(&mut self) -> Option<S> {
+        match self.user.take() {
+            Some(slot) if slot.state.as_any().is::<S>() => {
+                slot.state.into_any().downcast().ok().map(|b| *b)
+            }
+            other => {
+                self.user = other;
+                None
+            }
+        }
+    }
+
+    /// Translate a global index, e.g. from a `ParseError`, into the source location it refers to.
+    pub fn location(&self, ix: usize) -> Location<'_> {
+        self.sources.locate(ix)
+    }
+
+    /// Return the location of the current position.
+    pub fn current_location(&self) -> Location<'_> {
+        self.sources.locate(self.global)
+    }
+
+    /// Return the name of the source currently being parsed.
+    pub fn source_name(&self) -> &str {
+        self.sources.source_name(self.global)
+    }
+
+    /// Return the column (starting at 1) of the current position.
+    pub fn column(&self) -> usize {
+        self.sources.locate(self.global).column
+    }
+
+    /// Return the column of the innermost indented block being parsed, or 1 (the first column) if
+    /// there is none. See `combinators::IndentedBlock`.
+    pub fn indentation(&self) -> usize {
+        self.indents.last().copied().unwrap_or(1)
+    }
+
+    pub(crate) fn push_indentation(&mut self, column: usize) {
+        self.indents.push(column);
+    }
+
+    pub(crate) fn pop_indentation(&mut self) {
+        self.indents.pop();
+    }
+
+    /// Remove all results cached by memoizing parsers (see `combinators::Memo`). This is only
+    /// necessary if their results depend on something else than the input, e.g. user state or
+    /// indentation.
+    pub fn clear_memo(&mut self) {
+        self.memo.clear();
+    }
+
+    /// Return the result of the memoizing parser `key` at the current position, and the index at
+    /// which it stopped.
+    pub(crate) fn memo_get<R: Clone + 'static>(
+        &self,
+        key: MemoKey,
+    ) -> Option<(ParseResult<R>, usize)> {
+        self.memo.get(&(key, self.global)).and_then(|e| {
+            e.result
+                .downcast_ref::<ParseResult<R>>()
+                .map(|r| (r.clone(), e.end))
+        })
+    }
+
+    /// Store the result of the memoizing parser `key` having started at `start`. `end` is the
+    /// index at which it stopped.
+    pub(crate) fn memo_insert<R: 'static>(
+        &mut self,
+        key: MemoKey,
+        start: usize,
+        result: ParseResult<R>,
+        end: usize,
+    ) {
+        self.memo.insert(
+            (key, start),
+            MemoEntry {
+                end,
+                result: Box::new(result),
+            },
+        );
+    }
+
+    /// Advance to global index `ix`, which must have been consumed before.
+    pub(crate) fn skip_to(&mut self, ix: usize) {
+        assert!(ix >= self.global && self.current + (ix - self.global) <= self.buf.len());
+        self.current += ix - self.global;
+        self.global = ix;
+    }
+
+    /// Drop memoized results starting before the beginning of the buffer, as they can't be used
+    /// anymore.
+    fn forget_collected_memo(&mut self) {
+        let first = self.global - self.current;
+        self.memo.retain(|&(_, start), _| start >= first);
+    }
+
     /// Remember the current position in the input and protect it from buffer garbage collection.
     pub fn hold(&mut self) -> Hold {
         if self.oldest_hold_count.is_none() {
@@ -103,7 +339,14 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
                 self.oldest_hold_count = Some((self.global, count + 1));
             }
         }
-        Hold::new(self.global)
+        let snapshot = match self.user {
+            Some(UserSlot {
+                ref state,
+                rollback: true,
+            }) => Some((**state).clone_box()),
+            _ => None,
+        };
+        Hold::new(self.global, snapshot)
     }
 
     /// Notifiy the ParseState that a `Hold` is no longer needed (and the referenced piece of input
@@ -136,6 +379,9 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
         }
         self.current -= self.global - h.ix;
         self.global = h.ix;
+        if let (Some(slot), Some(state)) = (self.user.as_mut(), h.user.take()) {
+            slot.state = state;
+        }
         h.defuse();
     }
 
@@ -162,6 +408,7 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
                     .resize(self.buf.len() - self.current + 1, 0 as char);
                 self.current = 1;
                 // self.global remains untouched.
+                self.forget_collected_memo();
                 true
             }
             Some((ix, _)) => {
@@ -181,6 +428,7 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
                 self.buf.rotate_left(ix - 1);
                 self.buf.resize(self.buf.len() - ix + 1, 0 as char);
                 self.current = 1;
+                self.forget_collected_memo();
                 true
             }
             _ => false,
@@ -199,11 +447,40 @@ impl<Iter: Iterator<Item = char>> ParseState<Iter> {
         self.global -= 1;
     }
 
+    /// Consume input up to (not including) the next occurrence of `lit`, and return it. If `lit`
+    /// does not occur in the remaining input, nothing is consumed and `None` is returned.
+    pub(crate) fn take_until_literal(&mut self, lit: &[char]) -> Option<String> {
+        let mut from = self.current;
+        loop {
+            let found = if lit.is_empty() {
+                Some(from)
+            } else {
+                self.buf[from..]
+                    .windows(lit.len())
+                    .position(|w| w == lit)
+                    .map(|p| from + p)
+            };
+            if let Some(end) = found {
+                let s = self.buf[self.current..end].iter().collect();
+                self.global += end - self.current;
+                self.current = end;
+                return Some(s);
+            }
+            // A match may start in the part already searched.
+            from = from.max((self.buf.len() + 1).saturating_sub(lit.len()));
+            if !self.prefill(Self::PREFILL_DEFAULT) {
+                self.next = None;
+                return None;
+            }
+        }
+    }
+
     /// Fill buffer from source with at most `n` characters.
     fn prefill(&mut self, n: usize) -> bool {
         if let Some(next) = self.next.as_mut() {
             let oldlen = self.buf.len();
             self.buf.extend(next.take(n));
+            self.sources.feed(&self.buf[oldlen..]);
             return (self.buf.len() - oldlen) > 0;
         }
         false
@@ -289,7 +566,76 @@ mod tests {
         assert_eq!(Some('H'), ps.next());
         assert_eq!(
             Ok("üð".to_string()),
-            primitives::StringParser::new("üð".to_string()).parse(&mut ps)
+            primitives::StringParser::new("üð").parse(&mut ps)
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let mut ps = ParseState::new("a\nbc;\nd");
+        ps.set_source_name("main");
+        let mut p = primitives::string_none_of(";", crate::combinators::RepeatSpec::Any);
+        assert_eq!(Ok("a\nbc".to_string()), p.parse(&mut ps));
+        assert_eq!("main:2:3", ps.current_location().to_string());
+
+        ps.include("inc", "x\nyz");
+        assert_eq!("inc", ps.source_name());
+        assert_eq!("inc:1:1", ps.current_location().to_string());
+        let mut all = primitives::string_none_of("", crate::combinators::RepeatSpec::Any);
+        assert_eq!(Ok("x\nyz;\nd".to_string()), all.parse(&mut ps));
+
+        // Locations before, in, and after the included content.
+        assert_eq!("main:2:2", ps.location(3).to_string());
+        assert_eq!("inc:2:2", ps.location(7).to_string());
+        assert_eq!("main:2:3", ps.location(8).to_string());
+        assert_eq!("main:3:1", ps.location(10).to_string());
+        assert!(ps.finished());
+
+        let mut ps = ParseState::new("ab");
+        ps.include("inc", "1");
+        let err = primitives::StringParser::new("1b")
+            .parse(&mut ps)
+            .unwrap_err();
+        assert_eq!(
+            "<input>:1:1",
+            ps.location(err.position().unwrap()).to_string()
         );
     }
+
+    #[test]
+    fn test_memo_gc() {
+        let input = "a".repeat(10000);
+        let mut ps = ParseState::new(&input);
+        let key = MemoKey::Name("a");
+        let mut p = primitives::StringParser::new("a");
+        for _ in 0..input.len() {
+            let start = ps.index();
+            let hold = ps.hold();
+            let r = p.parse(&mut ps);
+            ps.memo_insert(key, start, r, ps.global);
+            ps.release(hold);
+        }
+        // Entries for collected input have been dropped.
+        assert!(ps.memo.len() < input.len() / 2);
+        assert!(ps
+            .memo
+            .keys()
+            .all(|&(_, start)| start >= ps.global - ps.current));
+    }
+
+    #[test]
+    fn test_include_nested() {
+        let mut ps = ParseState::new("ab");
+        ps.next();
+        ps.include("outer", "12");
+        ps.next();
+        ps.include("inner", "x");
+        let rest: String = (&mut ps).collect();
+        assert_eq!("x2b", rest);
+        assert_eq!("<input>:1:1", ps.location(0).to_string());
+        assert_eq!("outer:1:1", ps.location(1).to_string());
+        assert_eq!("inner:1:1", ps.location(2).to_string());
+        assert_eq!("outer:1:2", ps.location(3).to_string());
+        assert_eq!("<input>:1:2", ps.location(4).to_string());
+    }
 }